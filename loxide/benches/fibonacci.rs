@@ -0,0 +1,49 @@
+//! Times `fib` from `Lox/FibonacciRecursive.lox`, which spends nearly all of its time in calls.
+//!
+//! Run with `cargo bench --bench fibonacci [-- n]`, `n` defaults to 30.
+//!
+//! Before functions were shared by reference, every call copied the whole `FunctionObject` of
+//! the callee into its frame. The `clone_calls` feature puts that copy back, so running
+//! `cargo bench --bench fibonacci --features clone_calls` as well shows the speedup. Functions
+//! now also carry the source spans and the constant index of the compiler, so the copies cost
+//! somewhat more than they did before functions were shared.
+
+use loxide::{Value, Vm};
+use std::{env, time::Instant};
+
+const SAMPLE: &str = include_str!("../../Lox/FibonacciRecursive.lox");
+const RUNS: usize = 5;
+
+fn main() {
+    let n: u32 = env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(30);
+
+    // Only the declaration of `fib`, the rest of the sample times `fib(40)` itself
+    let declaration = &SAMPLE[..SAMPLE.find("\nvar ").unwrap_or(SAMPLE.len())];
+    let mut vm = Vm::new();
+    let script = vm.compile(declaration).expect("the sample compiles");
+    vm.interpret(script).expect("the sample runs");
+    let fib = vm.get_global("fib").expect("the sample declares fib");
+
+    let mut times = Vec::with_capacity(RUNS);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let result = vm.call_function(&fib, &[Value::Number(n.into())]).unwrap();
+        times.push(start.elapsed());
+        assert!(matches!(result, Value::Number(_)));
+    }
+    times.sort();
+
+    let mode = if cfg!(feature = "clone_calls") {
+        "copying functions on every call"
+    } else {
+        "sharing functions"
+    };
+    println!(
+        "fib({n}) {mode}: median {:?}, min {:?} over {RUNS} runs",
+        times[RUNS / 2],
+        times[0]
+    );
+}
@@ -0,0 +1,519 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Mul, Neg, Sub},
+};
+
+/// Largest integer such that it and every integer below it can be represented exactly as a `f64`
+pub const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+const DECIMAL_CHUNK: u32 = 1_000_000_000;
+const DECIMAL_CHUNK_DIGITS: usize = 9;
+
+/// Base that [`BigInt::to_str_radix`] can format numbers in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Decimal,
+    /// Prefixed with `0x`
+    Hexadecimal,
+}
+
+/// Arbitrary-precision signed integer
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BigInt {
+    negative: bool,
+    // Little-endian base 2^32 digits without trailing zeroes, empty for zero
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        trim(&mut magnitude);
+        Self {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    /// Number of bytes used by the digits
+    pub(crate) fn heap_size(&self) -> usize {
+        self.magnitude.capacity() * std::mem::size_of::<u32>()
+    }
+
+    pub fn from_u64(value: u64) -> Self {
+        Self::from_parts(false, vec![value as u32, (value >> 32) as u32])
+    }
+
+    pub fn from_i64(value: i64) -> Self {
+        let mut result = Self::from_u64(value.unsigned_abs());
+        result.negative = value < 0;
+        result
+    }
+
+    /// Converts an integral float exactly, returns `None` for fractional or non-finite values
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() || value.fract() != 0.0 {
+            return None;
+        }
+        if value == 0.0 {
+            return Some(Self::default());
+        }
+
+        let bits = value.abs().to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        // Integral non-zero floats are always normal and at least 1, so `shift` is above -53
+        let shift = exponent - 1075;
+
+        let magnitude = if shift >= 0 {
+            shl_magnitude(&Self::from_u64(mantissa).magnitude, shift as usize)
+        } else {
+            Self::from_u64(mantissa >> -shift).magnitude
+        };
+        Some(Self::from_parts(value < 0.0, magnitude))
+    }
+
+    /// Nearest float to this integer
+    pub fn to_f64(&self) -> f64 {
+        let value = self
+            .magnitude
+            .iter()
+            .rev()
+            .fold(0.0, |acc, digit| acc * 4_294_967_296.0 + *digit as f64);
+
+        if self.negative {
+            -value
+        } else {
+            value
+        }
+    }
+
+    /// Returns the value if it fits into an `i64`
+    pub fn to_i64(&self) -> Option<i64> {
+        let magnitude = match self.magnitude.as_slice() {
+            [] => 0,
+            [low] => *low as u64,
+            [low, high] => (*high as u64) << 32 | *low as u64,
+            _ => return None,
+        };
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+
+    /// Returns the value as a float if it lies within the exactly representable integer range
+    pub fn to_safe_f64(&self) -> Option<f64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let value = self.to_f64();
+        (value.abs() <= MAX_SAFE_INTEGER).then_some(value)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Truncating division, returns `None` when dividing by zero
+    pub fn div_rem(&self, divisor: &BigInt) -> Option<(BigInt, BigInt)> {
+        if divisor.is_zero() {
+            return None;
+        }
+
+        let (quotient, remainder) = div_rem_magnitude(&self.magnitude, &divisor.magnitude);
+        Some((
+            Self::from_parts(self.negative != divisor.negative, quotient),
+            Self::from_parts(self.negative, remainder),
+        ))
+    }
+
+    /// Parses a decimal or `0x`-prefixed hexadecimal integer with an optional sign
+    pub fn parse(text: &str) -> Option<Self> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+
+        let (radix, digits) = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            Some(digits) => (16, digits),
+            None => (10, text),
+        };
+
+        if digits.is_empty() {
+            return None;
+        }
+
+        let mut magnitude = Vec::new();
+        for c in digits.chars() {
+            let digit = c.to_digit(radix)?;
+            mul_add_small(&mut magnitude, radix, digit);
+        }
+
+        Some(Self::from_parts(negative, magnitude))
+    }
+
+    /// Formats the number in base 10 or 16
+    pub fn to_str_radix(&self, radix: Radix) -> String {
+        let mut digits = String::new();
+
+        match radix {
+            Radix::Hexadecimal => {
+                for (i, digit) in self.magnitude.iter().rev().enumerate() {
+                    if i == 0 {
+                        digits.push_str(&format!("{digit:x}"));
+                    } else {
+                        digits.push_str(&format!("{digit:08x}"));
+                    }
+                }
+            }
+            Radix::Decimal => {
+                let mut chunks = Vec::new();
+                let mut magnitude = self.magnitude.clone();
+                while !magnitude.is_empty() {
+                    let (quotient, remainder) = div_rem_small(&magnitude, DECIMAL_CHUNK);
+                    chunks.push(remainder);
+                    magnitude = quotient;
+                }
+
+                for (i, chunk) in chunks.iter().rev().enumerate() {
+                    if i == 0 {
+                        digits.push_str(&chunk.to_string());
+                    } else {
+                        digits.push_str(&format!("{chunk:0width$}", width = DECIMAL_CHUNK_DIGITS));
+                    }
+                }
+            }
+        }
+
+        if digits.is_empty() {
+            digits.push('0');
+        }
+
+        let prefix = match radix {
+            Radix::Decimal => "",
+            Radix::Hexadecimal => "0x",
+        };
+        let sign = if self.negative { "-" } else { "" };
+        format!("{sign}{prefix}{digits}")
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: Self) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::from_parts(
+                self.negative,
+                add_magnitude(&self.magnitude, &rhs.magnitude),
+            );
+        }
+
+        match cmp_magnitude(&self.magnitude, &rhs.magnitude) {
+            Ordering::Less => {
+                BigInt::from_parts(rhs.negative, sub_magnitude(&rhs.magnitude, &self.magnitude))
+            }
+            _ => BigInt::from_parts(
+                self.negative,
+                sub_magnitude(&self.magnitude, &rhs.magnitude),
+            ),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: Self) -> BigInt {
+        self + &-rhs
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: Self) -> BigInt {
+        BigInt::from_parts(
+            self.negative != rhs.negative,
+            mul_magnitude(&self.magnitude, &rhs.magnitude),
+        )
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.magnitude.clone())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => cmp_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq<f64> for BigInt {
+    fn eq(&self, other: &f64) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd<f64> for BigInt {
+    fn partial_cmp(&self, other: &f64) -> Option<Ordering> {
+        if other.is_nan() {
+            None
+        } else if other.is_infinite() {
+            Some(if *other > 0.0 {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            })
+        } else {
+            // Comparing with the floor is exact: an integer at or below the floor
+            // of a fractional number is always smaller than the number itself
+            let floor = BigInt::from_f64(other.floor()).unwrap();
+            match self.cmp(&floor) {
+                Ordering::Equal if other.fract() != 0.0 => Some(Ordering::Less),
+                ordering => Some(ordering),
+            }
+        }
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_str_radix(Radix::Decimal))
+    }
+}
+
+fn trim(magnitude: &mut Vec<u32>) {
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+
+    let mut result = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, digit) in long.iter().enumerate() {
+        let sum = *digit as u64 + *short.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry != 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// Requires `a >= b`
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, digit) in a.iter().enumerate() {
+        let mut difference = *digit as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        if difference < 0 {
+            difference += 1 << 32;
+            borrow = 1;
+        } else {
+            borrow = 0;
+        }
+        result.push(difference as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let product = *x as u64 * *y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_add_small(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for digit in magnitude.iter_mut() {
+        let value = *digit as u64 * factor as u64 + carry;
+        *digit = value as u32;
+        carry = value >> 32;
+    }
+    if carry != 0 {
+        magnitude.push(carry as u32);
+    }
+}
+
+fn div_rem_small(magnitude: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0; magnitude.len()];
+    let mut remainder = 0u64;
+    for (i, digit) in magnitude.iter().enumerate().rev() {
+        let value = (remainder << 32) | *digit as u64;
+        quotient[i] = (value / divisor as u64) as u32;
+        remainder = value % divisor as u64;
+    }
+    trim(&mut quotient);
+    (quotient, remainder as u32)
+}
+
+fn shl_magnitude(magnitude: &[u32], shift: usize) -> Vec<u32> {
+    let (digits, bits) = (shift / 32, shift % 32);
+
+    let mut result = vec![0; digits];
+    if bits == 0 {
+        result.extend_from_slice(magnitude);
+    } else {
+        let mut carry = 0;
+        for digit in magnitude {
+            result.push((digit << bits) | carry);
+            carry = digit >> (32 - bits);
+        }
+        result.push(carry);
+    }
+    trim(&mut result);
+    result
+}
+
+fn div_rem_magnitude(dividend: &[u32], divisor: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(dividend, divisor) == Ordering::Less {
+        return (Vec::new(), dividend.to_vec());
+    }
+
+    if let [divisor] = divisor {
+        let (quotient, remainder) = div_rem_small(dividend, *divisor);
+        let mut remainder = vec![remainder];
+        trim(&mut remainder);
+        return (quotient, remainder);
+    }
+
+    // Binary long division, simple and fast enough for the sizes scripts work with
+    let mut quotient = vec![0u32; dividend.len()];
+    let mut remainder = Vec::with_capacity(divisor.len() + 1);
+
+    for i in (0..dividend.len() * 32).rev() {
+        remainder = shl_magnitude(&remainder, 1);
+        if dividend[i / 32] >> (i % 32) & 1 == 1 {
+            if remainder.is_empty() {
+                remainder.push(1);
+            } else {
+                remainder[0] |= 1;
+            }
+        }
+
+        if cmp_magnitude(&remainder, divisor) != Ordering::Less {
+            remainder = sub_magnitude(&remainder, divisor);
+            quotient[i / 32] |= 1 << (i % 32);
+        }
+    }
+
+    trim(&mut quotient);
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BigInt, Radix};
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parse_and_format() {
+        let value = big("-123456789012345678901234567890");
+        assert_eq!("-123456789012345678901234567890", value.to_string());
+        assert_eq!(
+            "-0x18ee90ff6c373e0ee4e3f0ad2",
+            value.to_str_radix(Radix::Hexadecimal)
+        );
+        assert_eq!(value, big("-0x18EE90FF6C373E0EE4E3F0AD2"));
+        assert_eq!("0", big("-0").to_string());
+        assert!(BigInt::parse("12a").is_none());
+        assert!(BigInt::parse("0x").is_none());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = big("340282366920938463463374607431768211456");
+        let b = big("18446744073709551617");
+
+        assert_eq!(big("340282366920938463481821351505477763073"), &a + &b);
+        assert_eq!(big("340282366920938463444927863358058659839"), &a - &b);
+        assert_eq!(big("-340282366920938463444927863358058659839"), &b - &a);
+        assert_eq!(
+            big("6277101735386680764176071790128604879565730051895802724352"),
+            &a * &b
+        );
+
+        let (quotient, remainder) = a.div_rem(&b).unwrap();
+        assert_eq!(big("18446744073709551615"), quotient);
+        assert_eq!(big("1"), remainder);
+
+        let (quotient, remainder) = (-&a).div_rem(&big("7")).unwrap();
+        assert_eq!(big("-48611766702991209066196372490252601636"), quotient);
+        assert_eq!(big("-4"), remainder);
+
+        assert!(a.div_rem(&BigInt::default()).is_none());
+    }
+
+    #[test]
+    fn float_conversions() {
+        assert_eq!(
+            big("9007199254740993"),
+            &big("9007199254740992") + &big("1")
+        );
+        assert_eq!(
+            big("1000000000000000000000"),
+            BigInt::from_f64(1e21).unwrap()
+        );
+        assert_eq!(big("-4096"), BigInt::from_f64(-4096.0).unwrap());
+        assert!(BigInt::from_f64(0.5).is_none());
+        assert_eq!(Some(-4096.0), big("-4096").to_safe_f64());
+        assert_eq!(None, big("9007199254740992").to_safe_f64());
+
+        assert!(big("10") > 9.5);
+        assert!(big("9") < 9.5);
+        assert!(big("-9") > -9.5);
+        assert!(big("10") == 10.0);
+        assert!(big("10") < f64::INFINITY);
+        assert!(big("10").partial_cmp(&f64::NAN).is_none());
+    }
+}
@@ -1,40 +1,90 @@
-use crate::value::Value;
+use crate::{gc::Gc, object::Object, op_code::OpCode, value::Value};
+use std::{collections::HashMap, ops::Range};
 
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct Chunk {
     pub code: Vec<u8>,
     // Simple run-length encoding
-    lines: Vec<LineInfo>,
+    locations: Vec<LocationInfo>,
     pub constants: Vec<Value>,
+    /// Indices of the constants that are reused when they're added again
+    constant_indices: HashMap<ConstantKey, usize>,
+}
+
+/// Constants are deduplicated by value, numbers by their bits so that `0` and `-0` stay
+/// distinct
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum ConstantKey {
+    Number(u64),
+    String(Gc<str>),
+}
+
+impl ConstantKey {
+    fn new(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(number) => Some(ConstantKey::Number(number.to_bits())),
+            Value::Object(Object::String(str)) => Some(ConstantKey::String(str.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// Position in the source code that an instruction was compiled from
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SourceLocation {
+    pub line: u32,
+    pub column: u32,
+    /// Start of the byte range in the source
+    pub start: usize,
+    /// End of the byte range in the source
+    pub end: usize,
+}
+
+impl SourceLocation {
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.end
+    }
 }
 
 #[derive(Default, Debug, PartialEq, Clone)]
-struct LineInfo {
+struct LocationInfo {
     start_offset: usize,
-    line: u32,
+    location: SourceLocation,
 }
 
 impl Chunk {
-    pub fn write(&mut self, data: impl Into<u8>, line: u32) {
+    pub fn write(&mut self, data: impl Into<u8>, location: SourceLocation) {
         self.code.push(data.into());
 
-        if !self
-            .lines
+        if self
+            .locations
             .last()
-            .is_some_and(|last_line| last_line.line == line)
+            .is_none_or(|last| last.location != location)
         {
-            self.lines.push(LineInfo {
+            self.locations.push(LocationInfo {
                 start_offset: self.code.len() - 1,
-                line,
+                location,
             });
         }
     }
 
-    pub fn write_slice(&mut self, data: &[u8], line: u32) {
+    /// Removes the code from `len` on, so that the compiler can replace code it simplified
+    pub fn truncate(&mut self, len: usize) {
+        self.code.truncate(len);
+        self.locations.retain(|info| info.start_offset < len);
+    }
+
+    /// Removes the constants from `len` on, once the code that used them has been truncated
+    pub fn truncate_constants(&mut self, len: usize) {
+        self.constants.truncate(len);
+        self.constant_indices.retain(|_, index| *index < len);
+    }
+
+    pub fn write_slice(&mut self, data: &[u8], location: SourceLocation) {
         self.code.reserve(data.len());
 
         for byte in data {
-            self.write(*byte, line);
+            self.write(*byte, location);
         }
     }
 
@@ -53,9 +103,7 @@ impl Chunk {
     }
 
     #[cfg(any(feature = "print", feature = "trace"))]
-    pub fn disassemble_instruction(&self, mut offset: usize) -> usize {
-        use crate::op_code::OpCode;
-
+    pub fn disassemble_instruction(&self, offset: usize) -> usize {
         let code = self.code[offset];
 
         print!("{offset:04} ");
@@ -71,73 +119,176 @@ impl Chunk {
 
         use OpCode::*;
         match op_code {
-            LongConstant => {
-                let mut index_data = [0; 4];
-                index_data[0..3].copy_from_slice(&self.code[offset + 1..offset + 4]);
-
-                let index = u32::from_le_bytes(index_data);
-                let value = &self.constants[index as usize];
-
-                println!("{name:<16} {index} '{value}'");
-
-                offset += 3;
-            }
-            Constant | DefineGlobal | SetGlobal | GetGlobal | Call => {
-                let this = &self;
-                let name: &str = &name;
-                let offset: &mut usize = &mut offset;
-                *offset += 1;
-
-                let index = this.code[*offset];
-                let value = &this.constants[index as usize];
+            Constant | LongConstant => {
+                let index = self.read_index(op_code, offset + 1);
+                let value = &self.constants[index];
                 println!("{name:<16} {index} '{value}'");
             }
-            GetLocal | SetLocal => {
-                offset += 1;
-
-                let slot = self.code[offset];
+            GetLocal | SetLocal | DefineGlobal | GetGlobal | SetGlobal | LongGetLocal
+            | LongSetLocal | LongDefineGlobal | LongGetGlobal | LongSetGlobal => {
+                let slot = self.read_index(op_code, offset + 1);
                 println!("{name:<16} {slot:04}");
             }
-            Jump | JumpIfFalse => {
-                let jump =
-                    u16::from_ne_bytes(self.code[offset + 1..offset + 3].try_into().unwrap());
+            Call | TailCall => {
+                let arg_count = self.code[offset + 1];
+                println!("{name:<16} {arg_count}");
+            }
+            Invoke | LongInvoke => {
+                let index = self.read_index(op_code, offset + 1);
+                let arg_count = self.code[offset + op_code.operand_len()];
+                let method = &self.constants[index];
+                println!("{name:<16} {index} '{method}' ({arg_count} args)");
+            }
+            Jump | JumpIfFalse | Loop | LongJump | LongJumpIfFalse | LongLoop => {
+                let jump = self.jump_distance(offset);
                 println!("{name:<16} {jump:04}");
-
-                offset += 2;
             }
             _ => println!("{name}"),
         }
-        offset += 1;
-        offset
+        offset + 1 + op_code.operand_len()
     }
 
+    /// Reads the index operand of an instruction at `offset`
+    #[cfg(any(feature = "print", feature = "trace"))]
+    fn read_index(&self, op_code: OpCode, offset: usize) -> usize {
+        if op_code.is_long() {
+            let data = &self.code[offset..offset + 3];
+            u32::from_le_bytes([data[0], data[1], data[2], 0]) as usize
+        } else {
+            self.code[offset] as usize
+        }
+    }
+
+    /// Adds a constant or returns the index of an equal number or string that was added before.
+    /// Other constants, such as functions, are always added.
     pub fn add_constant(&mut self, value: impl Into<Value>) -> usize {
-        self.constants.push(value.into());
-        self.constants.len() - 1
+        let value = value.into();
+        let index = self.constants.len();
+
+        if let Some(key) = ConstantKey::new(&value) {
+            if let Some(existing) = self.constant_indices.get(&key) {
+                return *existing;
+            }
+            self.constant_indices.insert(key, index);
+        }
+        self.constants.push(value);
+        index
+    }
+
+    /// Distance of the jump or loop instruction at `offset`, counted from the end of the
+    /// instruction
+    pub fn jump_distance(&self, offset: usize) -> usize {
+        let operand = &self.code[offset + 1..];
+        match OpCode::from_byte(self.code[offset]) {
+            Some(OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop) => {
+                u16::from_ne_bytes([operand[0], operand[1]]) as usize
+            }
+            Some(OpCode::LongJump | OpCode::LongJumpIfFalse | OpCode::LongLoop) => {
+                u32::from_ne_bytes([operand[0], operand[1], operand[2], operand[3]]) as usize
+            }
+            _ => panic!("Not a jump at offset {offset}"),
+        }
+    }
+
+    /// Rewrites the long jumps whose distance fits in 16 bits to the short form. The compiler
+    /// emits every forward jump in the long form because it doesn't know how far it will go.
+    ///
+    /// Shrinking a jump can only make the other jumps shorter, so a jump that fits before the
+    /// rewrite still fits after it.
+    pub fn relax_jumps(&mut self) {
+        use OpCode::*;
+
+        // Old offset, opcode and new opcode of every instruction
+        let mut instructions = Vec::new();
+        let mut offset = 0;
+        while offset < self.code.len() {
+            let op_code = OpCode::from_byte(self.code[offset]).expect("Invalid opcode");
+            let new_op_code = match op_code.short_jump() {
+                Some(short) if self.jump_distance(offset) <= u16::MAX as usize => short,
+                _ => op_code,
+            };
+            instructions.push((offset, op_code, new_op_code));
+            offset += 1 + op_code.operand_len();
+        }
+        if instructions.iter().all(|(_, old, new)| old == new) {
+            return;
+        }
+
+        // New offset of every old offset, bytes inside an instruction move with its start
+        let mut new_offsets = vec![0; self.code.len() + 1];
+        let mut new_offset = 0;
+        for (offset, op_code, new_op_code) in &instructions {
+            let len = 1 + op_code.operand_len();
+            new_offsets[*offset..*offset + len].fill(new_offset);
+            new_offset += 1 + new_op_code.operand_len();
+        }
+        new_offsets[self.code.len()] = new_offset;
+
+        let mut code = Vec::with_capacity(new_offset);
+        for (offset, op_code, new_op_code) in instructions {
+            let len = 1 + op_code.operand_len();
+            let (Jump | JumpIfFalse | Loop | LongJump | LongJumpIfFalse | LongLoop) = op_code
+            else {
+                code.extend_from_slice(&self.code[offset..offset + len]);
+                continue;
+            };
+
+            let distance = self.jump_distance(offset);
+            let end = new_offsets[offset] + 1 + new_op_code.operand_len();
+            let distance = if let Loop | LongLoop = op_code {
+                end - new_offsets[offset + len - distance]
+            } else {
+                new_offsets[offset + len + distance] - end
+            };
+
+            code.push(new_op_code.into());
+            if new_op_code.short_jump().is_some() {
+                code.extend_from_slice(&(distance as u32).to_ne_bytes());
+            } else {
+                code.extend_from_slice(&(distance as u16).to_ne_bytes());
+            }
+        }
+
+        for info in &mut self.locations {
+            info.start_offset = new_offsets[info.start_offset];
+        }
+        self.code = code;
     }
 
     pub fn line_at(&self, offset: usize) -> u32 {
-        for (i, info) in self.lines.iter().enumerate() {
+        self.location_at(offset).line
+    }
+
+    pub fn location_at(&self, offset: usize) -> SourceLocation {
+        for (i, info) in self.locations.iter().enumerate() {
             if info.start_offset > offset {
-                return self.lines[i - 1].line;
+                return self.locations[i - 1].location;
             }
         }
-        self.lines.last().unwrap().line
+        self.locations.last().unwrap().location
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Chunk;
-    use crate::op_code::OpCode;
+    use super::{Chunk, SourceLocation};
+    use crate::{object::FunctionObject, op_code::OpCode, value::Value};
+
+    fn line(line: u32) -> SourceLocation {
+        SourceLocation {
+            line,
+            column: 1,
+            ..Default::default()
+        }
+    }
 
     #[test]
     fn lines() {
         let mut chunk = Chunk::default();
-        chunk.write(OpCode::Return, 2); // offset 0
-        chunk.write(OpCode::Constant, 3); // offset 1
-        chunk.write(1, 3); // offset 2
-        chunk.write(OpCode::Return, 5); // offset 3
+        chunk.write(OpCode::Return, line(2)); // offset 0
+        chunk.write(OpCode::Constant, line(3)); // offset 1
+        chunk.write(1, line(3)); // offset 2
+        chunk.write(OpCode::Return, line(5)); // offset 3
 
         println!("{chunk:?}");
 
@@ -146,4 +297,23 @@ mod tests {
         assert_eq!(3, chunk.line_at(2));
         assert_eq!(5, chunk.line_at(3));
     }
+
+    #[test]
+    fn deduplicates_constants() {
+        let mut chunk = Chunk::default();
+        assert_eq!(0, chunk.add_constant(1.0));
+        assert_eq!(1, chunk.add_constant(Value::new_string("text")));
+        assert_eq!(0, chunk.add_constant(1.0));
+        assert_eq!(1, chunk.add_constant(Value::new_string("text")));
+
+        assert_eq!(2, chunk.add_constant(0.0));
+        assert_eq!(3, chunk.add_constant(-0.0));
+        assert_eq!(4, chunk.add_constant(f64::NAN));
+        assert_eq!(4, chunk.add_constant(f64::NAN));
+
+        let function = || Value::Object(FunctionObject::default().into());
+        assert_eq!(5, chunk.add_constant(function()));
+        assert_eq!(6, chunk.add_constant(function()));
+        assert_eq!(7, chunk.constants.len());
+    }
 }
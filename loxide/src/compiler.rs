@@ -1,21 +1,46 @@
 use crate::{
-    chunk::Chunk,
+    bigint::BigInt,
+    chunk::{Chunk, SourceLocation},
+    diagnostic::{Diagnostic, Severity},
     object::{FunctionObject, Object},
     op_code::OpCode,
     scanner::{Scanner, Token, TokenType},
-    value::Value,
+    value::{ArithmeticOp, ComparisonOp, Value},
+    vm::Vm,
 };
-use std::{ops::Range, rc::Rc};
+use std::{collections::HashMap, ops::Range, rc::Rc};
 
-pub fn compile(source: &str) -> Option<FunctionObject> {
+pub fn compile(source: &str) -> Result<FunctionObject, Vec<Diagnostic>> {
+    compile_with_warnings(source).map(|(function, _)| function)
+}
+
+/// Compiles the source, also returning the warnings on success.
+/// On failure the diagnostics contain both the errors and the warnings.
+pub fn compile_with_warnings(
+    source: &str,
+) -> Result<(FunctionObject, Vec<Diagnostic>), Vec<Diagnostic>> {
+    compile_in(source, None)
+}
+
+/// Compiles the source, registering the objects that the compiler allocates with the heap of
+/// `vm` if there is one
+pub(crate) fn compile_in<'src>(
+    source: &'src str,
+    vm: Option<&'src mut Vm>,
+) -> Result<(FunctionObject, Vec<Diagnostic>), Vec<Diagnostic>> {
     let scanner = Scanner::new(source);
 
     let mut parser = Parser {
         scanner,
         current: None,
         previous: None,
+        globals: HashMap::new(),
+        global_slots: HashMap::new(),
+        diagnostics: Vec::new(),
+        warnings: Vec::new(),
         had_error: false,
         panic_mode: false,
+        vm,
     };
 
     let mut compiler = Compiler::new(&mut parser, FunctionType::Script);
@@ -30,7 +55,39 @@ pub fn compile(source: &str) -> Option<FunctionObject> {
         .parser
         .consume(TokenType::Eof, "Expected end of expression");
 
-    compiler.end()
+    let mut function = compiler.end();
+
+    match parser.vm {
+        Some(vm) => vm.finish_compile(),
+        None => {
+            if let Some(function) = &mut function {
+                let mut names: Vec<_> = parser.global_slots.into_iter().collect();
+                names.sort_by_key(|(_, slot)| *slot);
+                function.unlinked_globals =
+                    Some(names.into_iter().map(|(name, _)| name.into()).collect());
+            }
+        }
+    }
+
+    let mut diagnostics = parser.diagnostics;
+    let allowed_warnings = &parser.scanner.allowed_warnings;
+    diagnostics.extend(
+        parser
+            .warnings
+            .into_iter()
+            .filter(|(warning, diagnostic)| {
+                !allowed_warnings
+                    .iter()
+                    .any(|(line, name)| *name == warning.name() && *line == diagnostic.line)
+            })
+            .map(|(_, diagnostic)| diagnostic),
+    );
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+
+    match function {
+        Some(function) => Ok((function, diagnostics)),
+        None => Err(diagnostics),
+    }
 }
 
 struct Compiler<'a, 'src> {
@@ -39,12 +96,44 @@ struct Compiler<'a, 'src> {
     parser: &'a mut Parser<'src>,
     locals: Vec<Local>,
     scope_depth: i32,
+    // Offset of the most recently emitted `Call` instruction
+    last_call: Option<usize>,
+    /// Where the code of the left operand of the infix operator being compiled starts
+    operand_start: Mark,
+}
+
+/// Position in the chunk that the compiler goes back to when it replaces code it simplified
+#[derive(Debug, Clone, Copy, Default)]
+struct Mark {
+    code: usize,
+    constants: usize,
 }
 
 #[derive(Debug)]
 struct Local {
     name: Option<Token>,
     depth: i32,
+    used: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Warning {
+    UnusedVariable,
+    UnreachableCode,
+    Shadowing,
+    UnusedValue,
+}
+
+impl Warning {
+    /// Name used to silence the warning with a `// lox:allow(name)` comment
+    fn name(self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "unused_variable",
+            Warning::UnreachableCode => "unreachable_code",
+            Warning::Shadowing => "shadowing",
+            Warning::UnusedValue => "unused_value",
+        }
+    }
 }
 
 enum FunctionType {
@@ -67,17 +156,22 @@ impl<'a, 'src> Compiler<'a, 'src> {
         let local = Local {
             name: None,
             depth: 0,
+            used: true,
         };
         Compiler {
             current_function: FunctionObject {
                 arity: 0,
                 chunk: Chunk::default(),
                 name,
+                vm_id: parser.vm.as_ref().map_or(0, |vm| vm.id()),
+                ..Default::default()
             },
             function_type,
             parser,
             locals: vec![local],
             scope_depth: 0,
+            last_call: None,
+            operand_start: Mark::default(),
         }
     }
 
@@ -85,15 +179,19 @@ impl<'a, 'src> Compiler<'a, 'src> {
         &mut self.current_function.chunk
     }
 
+    fn previous_location(&self) -> SourceLocation {
+        token_location(self.parser.previous.unwrap())
+    }
+
     fn emit_byte(&mut self, byte: impl Into<u8>) {
-        let line = self.parser.previous.unwrap().line;
-        self.current_chunk().write(byte.into(), line);
+        let location = self.previous_location();
+        self.current_chunk().write(byte.into(), location);
     }
 
     fn emit_bytes(&mut self, byte_1: impl Into<u8>, byte_2: impl Into<u8>) {
-        let line = self.parser.previous.unwrap().line;
+        let location = self.previous_location();
         self.current_chunk()
-            .write_slice(&[byte_1.into(), byte_2.into()], line);
+            .write_slice(&[byte_1.into(), byte_2.into()], location);
     }
 
     fn emit_return(&mut self) {
@@ -101,30 +199,68 @@ impl<'a, 'src> Compiler<'a, 'src> {
         self.emit_byte(OpCode::Return.as_byte());
     }
 
+    /// Emits the long form of a forward jump, which is shortened once the function is done if
+    /// the distance fits, see [`Chunk::relax_jumps`]
     fn emit_jump(&mut self, op_code: OpCode) -> usize {
-        self.emit_byte(op_code);
-        self.emit_byte(0xff);
-        self.emit_byte(0xff);
-        self.current_chunk().code.len() - 2
+        let long_form = op_code.long_form().expect("Jump has no long form");
+        let location = self.previous_location();
+        self.current_chunk()
+            .write_slice(&[long_form.into(), 0xff, 0xff, 0xff, 0xff], location);
+        self.current_chunk().code.len() - 4
+    }
+
+    /// Emits an instruction with a one byte index, or its long form if the index doesn't fit
+    fn emit_indexed(&mut self, op_code: OpCode, index: usize) {
+        match u8::try_from(index) {
+            Ok(index) => self.emit_bytes(op_code, index),
+            Err(_) => self.emit_long(op_code, index),
+        }
+    }
+
+    fn emit_long(&mut self, op_code: OpCode, index: usize) {
+        let long_form = op_code.long_form().expect("Instruction has no long form");
+        let [a, b, c, _] = (index as u32).to_le_bytes();
+        let location = self.previous_location();
+        self.current_chunk()
+            .write_slice(&[long_form.into(), a, b, c], location);
+    }
+
+    /// Without a VM the slots are only linked when the script runs, so the long form is always
+    /// used to make room for any slot
+    fn emit_global(&mut self, op_code: OpCode, slot: usize) {
+        if self.parser.vm.is_some() {
+            self.emit_indexed(op_code, slot);
+        } else {
+            self.emit_long(op_code, slot);
+        }
     }
 
     fn emit_constant(&mut self, value: Value) {
         let index = self.make_constant(value);
-        self.emit_bytes(OpCode::Constant, index);
+        self.emit_indexed(OpCode::Constant, index);
     }
 
-    fn make_constant(&mut self, value: Value) -> u8 {
+    fn make_constant(&mut self, value: Value) -> usize {
+        if let Some(vm) = &mut self.parser.vm {
+            vm.track_compiled(&value);
+        }
+
         let index = self.current_chunk().add_constant(value);
-        if index > u8::MAX as usize {
+        if index > OpCode::MAX_LONG_INDEX {
             self.parser.error("Too many constants in one chunk");
             0
         } else {
-            index as u8
+            index
         }
     }
 
     fn end(mut self) -> Option<FunctionObject> {
         self.emit_return();
+        self.current_chunk().relax_jumps();
+
+        // The outermost scope of a function body is never ended explicitly
+        let locals = std::mem::take(&mut self.locals);
+        self.check_unused(&locals);
 
         if self.parser.had_error {
             None
@@ -229,19 +365,16 @@ impl<'a, 'src> Compiler<'a, 'src> {
 
         compiler.block();
 
-        match compiler.end() {
-            Some(function) => {
-                let value = Value::Object(Object::Function(function));
-                let constant = self.make_constant(value);
-                self.emit_bytes(OpCode::Constant, constant);
-            }
-            None => eprintln!("Could not compile function"),
+        // Errors in the function body have already been reported to the parser
+        if let Some(function) = compiler.end() {
+            let value = Value::Object(Object::from(function));
+            self.emit_constant(value);
         }
     }
 
-    fn define_variable(&mut self, var_index: u8) {
+    fn define_variable(&mut self, slot: usize) {
         if self.scope_depth == 0 {
-            self.emit_bytes(OpCode::DefineGlobal, var_index);
+            self.emit_global(OpCode::DefineGlobal, slot);
         } else {
             self.mark_initialized();
         }
@@ -256,50 +389,116 @@ impl<'a, 'src> Compiler<'a, 'src> {
     fn declare_variable(&mut self) {
         if self.scope_depth != 0 {
             let name = self.parser.previous.unwrap();
+            let mut shadowed_local = None;
 
             for local in self.locals.iter().rev() {
+                let Some(local_name) = local
+                    .name
+                    .filter(|local_name| self.identifiers_eq(name, *local_name))
+                else {
+                    continue;
+                };
+
                 if local.depth != -1 && local.depth < self.scope_depth {
+                    shadowed_local = Some(local_name);
                     break;
                 }
 
-                if let Some(local_name) = local.name {
-                    if self.identifiers_eq(name, local_name) {
-                        self.parser
-                            .error("Variable with this name already exists in the current scope");
-                    }
-                }
+                self.parser.error_at_token(
+                    name,
+                    "Variable with this name already exists in the current scope",
+                    Some(format!("Previously declared on line {}", local_name.line)),
+                );
             }
 
+            self.check_shadowing(name, shadowed_local);
             self.add_local(name);
         }
     }
 
+    /// Warns if `name` shadows a local from an outer scope or a global variable
+    fn check_shadowing(&mut self, name: Token, shadowed_local: Option<Token>) {
+        let lexeme = self.parser.scanner.lexeme(name);
+
+        let (kind, shadowed) = match shadowed_local {
+            Some(local) => ("local", local),
+            None => match self.parser.globals.get(lexeme) {
+                Some(global) => ("global", *global),
+                None => return,
+            },
+        };
+
+        let message = format!("Variable '{lexeme}' shadows a {kind} variable");
+        let note = format!("Shadowed variable declared on line {}", shadowed.line);
+        self.parser.warning(
+            Warning::Shadowing,
+            token_location(name),
+            message,
+            Some(note),
+        );
+    }
+
+    fn check_unused(&mut self, locals: &[Local]) {
+        for local in locals.iter().filter(|local| !local.used) {
+            let Some(name) = local.name else { continue };
+            let lexeme = self.parser.scanner.lexeme(name);
+
+            if !lexeme.starts_with('_') {
+                let message = format!("Unused variable '{lexeme}'");
+                self.parser
+                    .warning(Warning::UnusedVariable, token_location(name), message, None);
+            }
+        }
+    }
+
     fn identifiers_eq(&self, a: Token, b: Token) -> bool {
         self.parser.scanner.source[a.start..a.end] == self.parser.scanner.source[b.start..b.end]
     }
 
     fn add_local(&mut self, name: Token) {
+        if self.locals.len() > OpCode::MAX_LONG_INDEX {
+            self.parser.error("Too many local variables in function");
+            return;
+        }
+
         let local = Local {
             name: Some(name),
             depth: -1,
+            used: false,
         };
         self.locals.push(local);
     }
 
-    fn parse_variable(&mut self, message: &str) -> u8 {
+    fn parse_variable(&mut self, message: &str) -> usize {
         self.parser.consume(TokenType::Identifier, message);
 
         self.declare_variable();
         if self.scope_depth > 0 {
             0
         } else {
-            self.identifier_constant(self.parser.previous.unwrap())
+            let name = self.parser.previous.unwrap();
+            let lexeme = self.parser.scanner.lexeme(name);
+            self.parser.globals.entry(lexeme).or_insert(name);
+            self.global_slot(name)
+        }
+    }
+
+    /// Resolves a global variable to its slot, which it has even if it isn't defined yet
+    fn global_slot(&mut self, name: Token) -> usize {
+        let lexeme = self.parser.scanner.lexeme(name);
+        let slot = self.parser.global_slot(lexeme);
+        if slot > OpCode::MAX_LONG_INDEX {
+            self.parser.error("Too many global variables");
+            0
+        } else {
+            slot
         }
     }
 
-    fn identifier_constant(&mut self, name: Token) -> u8 {
+    fn identifier_constant(&mut self, name: Token) -> usize {
         let name = &self.parser.scanner.source[name.start..name.end];
-        self.make_constant(Value::new_string(name))
+        let name = self.parser.string(name);
+        self.make_constant(name)
     }
 
     fn statement(&mut self) {
@@ -424,36 +623,64 @@ impl<'a, 'src> Compiler<'a, 'src> {
             self.expression();
             self.parser
                 .consume(TokenType::Semicolon, "Expected a ';' after return value");
+
+            // A call that is the last instruction of the returned expression is in tail
+            // position, so the callee can reuse the current frame
+            let code_len = self.current_chunk().code.len();
+            if let Some(offset) = self.last_call.filter(|offset| offset + 2 == code_len) {
+                self.current_chunk().code[offset] = OpCode::TailCall.into();
+            }
+
             self.emit_byte(OpCode::Return);
         }
     }
 
     fn patch_jump(&mut self, offset: usize) {
-        let jump = self.current_chunk().code.len() - offset - 2;
+        let jump = self.current_chunk().code.len() - offset - 4;
 
-        if jump > u16::MAX as usize {
+        let Ok(jump) = u32::try_from(jump) else {
             self.parser.error("Jump is too long");
-        }
-
-        let [a, b] = (jump as u16).to_ne_bytes();
-        self.current_chunk().code[offset] = a;
-        self.current_chunk().code[offset + 1] = b;
+            return;
+        };
+        self.current_chunk().code[offset..offset + 4].copy_from_slice(&jump.to_ne_bytes());
     }
 
     fn emit_loop(&mut self, loop_start: usize) {
-        self.emit_byte(OpCode::Loop);
-
-        let offset = self.current_chunk().code.len() - loop_start + 2;
-        if offset > u16::MAX as usize {
+        let start = self.current_chunk().code.len();
+        let location = self.previous_location();
+
+        // The distance includes the instruction itself
+        let short = start + 3 - loop_start;
+        if short <= u16::MAX as usize {
+            let [a, b] = (short as u16).to_ne_bytes();
+            self.current_chunk()
+                .write_slice(&[OpCode::Loop.into(), a, b], location);
+        } else if let Ok(long) = u32::try_from(start + 5 - loop_start) {
+            let [a, b, c, d] = long.to_ne_bytes();
+            self.current_chunk()
+                .write_slice(&[OpCode::LongLoop.into(), a, b, c, d], location);
+        } else {
             self.parser.error("Loop body is too big");
         }
-
-        let [a, b] = (offset as u16).to_ne_bytes();
-        self.emit_bytes(a, b);
     }
 
     fn block(&mut self) {
+        let mut returned = false;
+        let mut warned_unreachable = false;
+
         while !self.is_at_end() && !self.check_current_token(TokenType::RightBrace) {
+            if returned && !warned_unreachable {
+                let location = token_location(self.parser.current.unwrap());
+                self.parser.warning(
+                    Warning::UnreachableCode,
+                    location,
+                    "Unreachable code".to_owned(),
+                    Some("Any code following a return statement is never executed".to_owned()),
+                );
+                warned_unreachable = true;
+            }
+
+            returned |= self.check_current_token(TokenType::Return);
             self.declaration();
         }
 
@@ -468,23 +695,63 @@ impl<'a, 'src> Compiler<'a, 'src> {
     fn end_scope(&mut self) {
         self.scope_depth -= 1;
 
+        let mut scope_locals = Vec::new();
         while self
             .locals
             .last()
             .is_some_and(|top_var| top_var.depth > self.scope_depth)
         {
-            self.locals.pop().unwrap();
+            scope_locals.push(self.locals.pop().unwrap());
             self.emit_byte(OpCode::Pop);
         }
+
+        scope_locals.reverse();
+        self.check_unused(&scope_locals);
     }
 
     fn expression_statement(&mut self) {
+        let first_token = self.parser.current.unwrap();
+        let code_start = self.current_chunk().code.len();
+
         self.expression();
+
+        if !self.parser.panic_mode && !self.has_side_effects(code_start) {
+            let mut location = token_location(first_token);
+            location.end = self.parser.previous.unwrap().end;
+            self.parser.warning(
+                Warning::UnusedValue,
+                location,
+                "Expression result is unused".to_owned(),
+                Some("The expression has no side effects".to_owned()),
+            );
+        }
+
         self.parser
             .consume(TokenType::Semicolon, "Expected a ';' after expression");
         self.emit_byte(OpCode::Pop);
     }
 
+    /// Checks if the code emitted since `start` can do anything besides producing a value
+    fn has_side_effects(&mut self, start: usize) -> bool {
+        let code = &self.current_chunk().code;
+
+        let mut offset = start;
+        while offset < code.len() {
+            let Some(op_code) = OpCode::from_byte(code[offset]) else {
+                return true;
+            };
+
+            use OpCode::*;
+            if let Call | TailCall | Invoke | LongInvoke | SetGlobal | LongSetGlobal | SetLocal
+            | LongSetLocal | DefineGlobal | LongDefineGlobal | Print = op_code
+            {
+                return true;
+            }
+            offset += 1 + op_code.operand_len();
+        }
+        false
+    }
+
     fn print_statement(&mut self) {
         self.expression();
         self.parser
@@ -494,6 +761,15 @@ impl<'a, 'src> Compiler<'a, 'src> {
 
     fn number(&mut self, _can_assign: bool) {
         let lexeme = self.parser.scanner.lexeme(self.parser.previous.unwrap());
+
+        // Integer literals outside of the exact float range become big integers
+        if !lexeme.contains('.') {
+            if let Some(value) = BigInt::parse(lexeme) {
+                self.emit_constant(value.into());
+                return;
+            }
+        }
+
         match lexeme.parse::<f64>() {
             Ok(value) => self.emit_constant(Value::Number(value)),
             Err(_) => self.parser.error("Could not parse number"),
@@ -501,37 +777,120 @@ impl<'a, 'src> Compiler<'a, 'src> {
     }
 
     fn unary(&mut self, _can_assign: bool) {
-        let operator_type = self.parser.previous.unwrap().token_type;
+        let operator = self.parser.previous.unwrap();
+        let start = self.mark();
 
         self.parse_presedence(Precedence::Unary);
 
-        match operator_type {
-            TokenType::Minus => self.emit_byte(OpCode::Negate),
-            TokenType::Bang => self.emit_byte(OpCode::Not),
-            _ => (),
+        let op_code = match operator.token_type {
+            TokenType::Minus => OpCode::Negate,
+            TokenType::Bang => OpCode::Not,
+            _ => return,
+        };
+
+        let folded = self
+            .constant_at(start.code, self.current_function.chunk.code.len())
+            .and_then(|operand| fold_unary(op_code, &operand));
+        match folded {
+            Some(value) => self.replace_with_constant(start, value),
+            None => self.emit_operator(operator, op_code),
         }
     }
 
     fn binary(&mut self, _can_assign: bool) {
-        let operator_type = self.previous_token_type();
-        let rule = self.get_rule(operator_type);
+        let left_start = self.operand_start;
+        let operator = self.parser.previous.unwrap();
+        let rule = self.get_rule(operator.token_type);
+        let right_start = self.mark();
         self.parse_presedence(Precedence::from_byte(rule.precedence.as_byte() + 1).unwrap());
 
-        match operator_type {
-            TokenType::Plus => self.emit_byte(OpCode::Add),
-            TokenType::Minus => self.emit_byte(OpCode::Subtract),
-            TokenType::Star => self.emit_byte(OpCode::Multiply),
-            TokenType::Slash => self.emit_byte(OpCode::Divide),
-            TokenType::BangEqual => self.emit_bytes(OpCode::Equal, OpCode::Not),
-            TokenType::EqualEqual => self.emit_byte(OpCode::Equal),
-            TokenType::Greater => self.emit_byte(OpCode::Greater),
-            TokenType::GreaterEqual => self.emit_bytes(OpCode::Less, OpCode::Not),
-            TokenType::Less => self.emit_byte(OpCode::Less),
-            TokenType::LessEqual => self.emit_bytes(OpCode::Greater, OpCode::Not),
-            _ => (),
+        let op_code = match operator.token_type {
+            TokenType::Plus => OpCode::Add,
+            TokenType::Minus => OpCode::Subtract,
+            TokenType::Star => OpCode::Multiply,
+            TokenType::Slash => OpCode::Divide,
+            TokenType::Percent => OpCode::Modulo,
+            TokenType::BangEqual => OpCode::NotEqual,
+            TokenType::EqualEqual => OpCode::Equal,
+            TokenType::Greater => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::Less => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            _ => return,
+        };
+
+        let left = self.constant_at(left_start.code, right_start.code);
+        let right = self.constant_at(right_start.code, self.current_function.chunk.code.len());
+        let folded = left
+            .zip(right)
+            .and_then(|(left, right)| fold_binary(op_code, &left, &right));
+        match folded {
+            Some(value) => self.replace_with_constant(left_start, value),
+            None => self.emit_operator(operator, op_code),
+        }
+    }
+
+    /// Emits an instruction located at the operator token, so that runtime errors point at it
+    /// instead of the last operand
+    fn emit_operator(&mut self, operator: Token, op_code: OpCode) {
+        let location = token_location(operator);
+        self.current_chunk().write(op_code, location);
+    }
+
+    /// Returns the value loaded by the code between `start` and `end`, if it's a single
+    /// constant or literal
+    fn constant_at(&self, start: usize, end: usize) -> Option<Value> {
+        let chunk = &self.current_function.chunk;
+        let op_code = OpCode::from_byte(*chunk.code.get(start)?)?;
+        if start + 1 + op_code.operand_len() != end {
+            return None;
+        }
+
+        let operand = &chunk.code[start + 1..end];
+        match op_code {
+            OpCode::Nil => Some(Value::Nil),
+            OpCode::True => Some(Value::Boolean(true)),
+            OpCode::False => Some(Value::Boolean(false)),
+            OpCode::Constant => Some(chunk.constants[operand[0] as usize].clone()),
+            OpCode::LongConstant => {
+                let index = u32::from_le_bytes([operand[0], operand[1], operand[2], 0]);
+                Some(chunk.constants[index as usize].clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Replaces the code from `start` on with a constant computed at compile time
+    fn replace_with_constant(&mut self, start: Mark, value: Value) {
+        self.truncate(start);
+        match value {
+            Value::Nil => self.emit_byte(OpCode::Nil),
+            Value::Boolean(true) => self.emit_byte(OpCode::True),
+            Value::Boolean(false) => self.emit_byte(OpCode::False),
+            Value::Object(Object::String(str)) => {
+                let value = self.parser.string(&str);
+                self.emit_constant(value);
+            }
+            value => self.emit_constant(value),
+        }
+    }
+
+    fn mark(&mut self) -> Mark {
+        let chunk = self.current_chunk();
+        Mark {
+            code: chunk.code.len(),
+            constants: chunk.constants.len(),
         }
     }
 
+    /// Removes the code from `mark` on, together with the constants that only it used
+    fn truncate(&mut self, mark: Mark) {
+        let chunk = self.current_chunk();
+        chunk.truncate(mark.code);
+        chunk.truncate_constants(mark.constants);
+        self.last_call = None;
+    }
+
     fn grouping(&mut self, _can_assign: bool) {
         self.expression();
         self.parser
@@ -550,14 +909,15 @@ impl<'a, 'src> Compiler<'a, 'src> {
     fn string(&mut self, _can_assign: bool) {
         let previous_token = self.parser.previous.unwrap();
         let value = &self.parser.scanner.source[previous_token.start + 1..previous_token.end - 1];
-        self.emit_constant(Value::new_string(value));
+        let value = self.parser.string(value);
+        self.emit_constant(value);
     }
 
     fn variable(&mut self, can_assign: bool) {
         self.named_variable(self.parser.previous.unwrap(), can_assign);
     }
 
-    fn resolve_local(&mut self, name: Token) -> Option<u8> {
+    fn resolve_local(&mut self, name: Token) -> Option<usize> {
         self.locals
             .iter()
             .enumerate()
@@ -572,28 +932,54 @@ impl<'a, 'src> Compiler<'a, 'src> {
                     self.parser
                         .error("Cannot read local variable in its own initializer");
                 }
-                i as u8
+                i
             })
     }
 
     fn named_variable(&mut self, name: Token, can_assign: bool) {
         let (get_op, set_op, arg) = match self.resolve_local(name) {
             None => {
-                let arg = self.identifier_constant(name);
+                let arg = self.global_slot(name);
                 (OpCode::GetGlobal, OpCode::SetGlobal, arg)
             }
             Some(local) => (OpCode::GetLocal, OpCode::SetLocal, local),
         };
 
-        if can_assign && self.match_token(TokenType::Equal) {
+        let is_local = get_op == OpCode::GetLocal;
+        let op_code = if can_assign && self.match_token(TokenType::Equal) {
             self.expression();
-            self.emit_bytes(set_op, arg);
+            set_op
         } else {
-            self.emit_bytes(get_op, arg);
+            if is_local {
+                self.locals[arg].used = true;
+            }
+            get_op
+        };
+
+        if is_local {
+            self.emit_indexed(op_code, arg);
+        } else {
+            self.emit_global(op_code, arg);
         }
     }
 
     fn and(&mut self, _can_assign: bool) {
+        let left_start = self.operand_start;
+        if let Some(left) =
+            self.constant_at(left_start.code, self.current_function.chunk.code.len())
+        {
+            if left.is_falsey() {
+                // The right operand is never evaluated, the result is the left one
+                let right_start = self.mark();
+                self.parse_presedence(Precedence::And);
+                self.truncate(right_start);
+            } else {
+                self.truncate(left_start);
+                self.parse_presedence(Precedence::And);
+            }
+            return;
+        }
+
         let end_jump = self.emit_jump(OpCode::JumpIfFalse);
         self.emit_byte(OpCode::Pop);
         self.parse_presedence(Precedence::And);
@@ -601,6 +987,22 @@ impl<'a, 'src> Compiler<'a, 'src> {
     }
 
     fn or(&mut self, _can_assign: bool) {
+        let left_start = self.operand_start;
+        if let Some(left) =
+            self.constant_at(left_start.code, self.current_function.chunk.code.len())
+        {
+            if left.is_falsey() {
+                self.truncate(left_start);
+                self.parse_presedence(Precedence::Or);
+            } else {
+                // The right operand is never evaluated, the result is the left one
+                let right_start = self.mark();
+                self.parse_presedence(Precedence::Or);
+                self.truncate(right_start);
+            }
+            return;
+        }
+
         let else_jump = self.emit_jump(OpCode::JumpIfFalse);
         let end_jump = self.emit_jump(OpCode::Jump);
 
@@ -613,9 +1015,32 @@ impl<'a, 'src> Compiler<'a, 'src> {
 
     fn call(&mut self, _can_assign: bool) {
         let arg_count = self.argument_list();
+        self.last_call = Some(self.current_chunk().code.len());
         self.emit_bytes(OpCode::Call, arg_count);
     }
 
+    fn dot(&mut self, _can_assign: bool) {
+        self.parser
+            .consume(TokenType::Identifier, "Expected a method name after '.'");
+        let name_token = self.parser.previous.unwrap();
+        let name = self.identifier_constant(name_token);
+
+        // Values don't have fields, the only thing that can follow the name is a call
+        self.parser
+            .consume(TokenType::LeftParen, "Expected a '(' after the method name");
+        let arg_count = self.argument_list();
+
+        let location = token_location(name_token);
+        let instruction = match u8::try_from(name) {
+            Ok(name) => vec![OpCode::Invoke.into(), name, arg_count],
+            Err(_) => {
+                let [a, b, c, _] = (name as u32).to_le_bytes();
+                vec![OpCode::LongInvoke.into(), a, b, c, arg_count]
+            }
+        };
+        self.current_chunk().write_slice(&instruction, location);
+    }
+
     fn argument_list(&mut self) -> u8 {
         let mut arg_count = 0;
         if !self.check_current_token(TokenType::RightParen) {
@@ -640,6 +1065,7 @@ impl<'a, 'src> Compiler<'a, 'src> {
     }
 
     fn parse_presedence(&mut self, precedence: Precedence) {
+        let start = self.mark();
         self.parser.advance();
         let prefix_rule = self.get_rule(self.previous_token_type());
         let can_assign = precedence <= Precedence::Assignment;
@@ -658,6 +1084,7 @@ impl<'a, 'src> Compiler<'a, 'src> {
         while precedence <= self.get_rule(self.current_token_type()).precedence {
             self.parser.advance();
             let infix_rule = self.get_rule(self.previous_token_type()).infix.unwrap();
+            self.operand_start = start;
             infix_rule(self, can_assign);
         }
     }
@@ -666,10 +1093,12 @@ impl<'a, 'src> Compiler<'a, 'src> {
         use TokenType::*;
         match token_type {
             LeftParen => ParseRule::new(Some(Self::grouping), Some(Self::call), Precedence::Call),
+            Dot => ParseRule::new(None, Some(Self::dot), Precedence::Call),
             Minus => ParseRule::new(Some(Self::unary), Some(Self::binary), Precedence::Term),
             Plus => ParseRule::new(None, Some(Self::binary), Precedence::Term),
             Slash => ParseRule::new(None, Some(Self::binary), Precedence::Factor),
             Star => ParseRule::new(None, Some(Self::binary), Precedence::Factor),
+            Percent => ParseRule::new(None, Some(Self::binary), Precedence::Factor),
             Number => ParseRule::new(Some(Self::number), None, Precedence::None),
             False => ParseRule::new(Some(Self::literal), None, Precedence::None),
             True => ParseRule::new(Some(Self::literal), None, Precedence::None),
@@ -720,6 +1149,15 @@ impl<'a, 'src> Compiler<'a, 'src> {
     }
 }
 
+fn token_location(token: Token) -> SourceLocation {
+    SourceLocation {
+        line: token.line,
+        column: token.column,
+        start: token.start,
+        end: token.end,
+    }
+}
+
 type ParseFn<'comp, 'parser, 'src> = fn(&'comp mut Compiler<'parser, 'src>, bool);
 
 struct ParseRule<'src, 'parser, 'comp> {
@@ -742,15 +1180,81 @@ impl<'src, 'parser, 'comp> ParseRule<'src, 'parser, 'comp> {
     }
 }
 
+/// Evaluates an operator on a constant operand the way the VM does. Returns `None` if it would
+/// fail, so that the error is raised at runtime.
+fn fold_unary(op_code: OpCode, operand: &Value) -> Option<Value> {
+    match op_code {
+        OpCode::Negate => operand.negate(),
+        OpCode::Not => Some(operand.is_falsey().into()),
+        _ => None,
+    }
+}
+
+/// Evaluates an operator on constant operands the way the VM does. Returns `None` if it would
+/// fail, so that the error is raised at runtime.
+fn fold_binary(op_code: OpCode, left: &Value, right: &Value) -> Option<Value> {
+    match op_code {
+        OpCode::Add => match (left, right) {
+            (Value::Object(Object::String(a)), Value::Object(Object::String(b))) => {
+                Some(Value::new_string(format!("{a}{b}")))
+            }
+            _ => left.arithmetic(ArithmeticOp::Add, right),
+        },
+        OpCode::Subtract => left.arithmetic(ArithmeticOp::Subtract, right),
+        OpCode::Multiply => left.arithmetic(ArithmeticOp::Multiply, right),
+        OpCode::Divide => left.arithmetic(ArithmeticOp::Divide, right),
+        OpCode::Modulo => left.arithmetic(ArithmeticOp::Modulo, right),
+        OpCode::Equal => Some((left == right).into()),
+        OpCode::NotEqual => Some((left != right).into()),
+        OpCode::Greater => left.compare(ComparisonOp::Greater, right).map(Value::from),
+        OpCode::GreaterEqual => left
+            .compare(ComparisonOp::GreaterEqual, right)
+            .map(Value::from),
+        OpCode::Less => left.compare(ComparisonOp::Less, right).map(Value::from),
+        OpCode::LessEqual => left
+            .compare(ComparisonOp::LessEqual, right)
+            .map(Value::from),
+        _ => None,
+    }
+}
+
 struct Parser<'src> {
     scanner: Scanner<'src>,
     current: Option<Token>,
     previous: Option<Token>,
+    /// Global variables declared so far
+    globals: HashMap<&'src str, Token>,
+    /// Slots of the global variables used so far, when compiling without a VM
+    global_slots: HashMap<&'src str, usize>,
+    diagnostics: Vec<Diagnostic>,
+    /// Warnings are only filtered once the whole source is scanned, because a
+    /// `// lox:allow(...)` comment can come after the code it applies to on the same line
+    warnings: Vec<(Warning, Diagnostic)>,
     had_error: bool,
     panic_mode: bool,
+    /// VM that will run the compiled code, objects are allocated on its heap
+    vm: Option<&'src mut Vm>,
 }
 
 impl<'a> Parser<'a> {
+    fn global_slot(&mut self, name: &'a str) -> usize {
+        match &mut self.vm {
+            Some(vm) => vm.global_slot(name),
+            None => {
+                let next = self.global_slots.len();
+                *self.global_slots.entry(name).or_insert(next)
+            }
+        }
+    }
+
+    /// Creates a string constant, interned if there is a VM
+    fn string(&mut self, value: &str) -> Value {
+        match &mut self.vm {
+            Some(vm) => Value::new_string(vm.intern(value)),
+            None => Value::new_string(value),
+        }
+    }
+
     fn advance(&mut self) {
         self.previous = self.current.take();
 
@@ -761,7 +1265,7 @@ impl<'a> Parser<'a> {
                     break;
                 }
                 Err(err) => {
-                    self.error_at(Some(err.start..err.end), err.line, &err.message);
+                    self.error_at(err.start..err.end, err.line, err.column, &err.message, None);
                 }
             }
         }
@@ -779,31 +1283,70 @@ impl<'a> Parser<'a> {
     }
 
     fn error_at_current(&mut self, message: &str) {
-        let current = self.current.unwrap();
-        self.error_at(Some(current.start..current.end), current.line, message);
+        self.error_at_token(self.current.unwrap(), message, None);
     }
 
     fn error(&mut self, message: &str) {
-        let previous = self.previous.unwrap();
-        self.error_at(Some(previous.start..previous.end), previous.line, message);
+        self.error_at_token(self.previous.unwrap(), message, None);
+    }
+
+    fn error_at_token(&mut self, token: Token, message: &str, note: Option<String>) {
+        let note = note.or_else(|| {
+            (token.token_type == TokenType::Eof).then(|| "Reached the end of the source".to_owned())
+        });
+        self.error_at(
+            token.start..token.end,
+            token.line,
+            token.column,
+            message,
+            note,
+        );
     }
 
-    fn error_at(&mut self, range: Option<Range<usize>>, line: u32, message: &str) {
+    fn error_at(
+        &mut self,
+        span: Range<usize>,
+        line: u32,
+        column: u32,
+        message: &str,
+        note: Option<String>,
+    ) {
         if self.panic_mode {
             return;
         }
 
         self.panic_mode = true;
-        eprint!("[line {line}] Error");
-
-        if let Some(range) = range {
-            eprint!(" at '{}'", &self.scanner.source[range]);
-        } else {
-            eprint!(" at end");
-        }
-
-        eprintln!(": {message}");
         self.had_error = true;
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: message.to_owned(),
+            span,
+            line,
+            column,
+            note,
+        });
+    }
+
+    fn warning(
+        &mut self,
+        warning: Warning,
+        location: SourceLocation,
+        message: String,
+        note: Option<String>,
+    ) {
+        let note =
+            note.unwrap_or_else(|| format!("Silence with `// lox:allow({})`", warning.name()));
+        self.warnings.push((
+            warning,
+            Diagnostic {
+                severity: Severity::Warning,
+                message,
+                span: location.span(),
+                line: location.line,
+                column: location.column,
+                note: Some(note),
+            },
+        ));
     }
 }
 
@@ -821,3 +1364,385 @@ convertable_enum! {
     Call = 9 ,
     Primary = 10,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, compile_with_warnings};
+    use crate::{
+        diagnostic::{Diagnostic, Severity},
+        op_code::OpCode,
+        value::Value,
+        vm::{ErrorKind, Vm},
+    };
+
+    fn op_codes(code: &[u8]) -> Vec<OpCode> {
+        let mut ops = Vec::new();
+        let mut offset = 0;
+        while offset < code.len() {
+            let op_code = OpCode::from_byte(code[offset]).unwrap();
+            ops.push(op_code);
+            offset += 1 + op_code.operand_len();
+        }
+        ops
+    }
+
+    fn warnings(source: &str) -> Vec<(u32, String)> {
+        let (_, warnings) = compile_with_warnings(source).unwrap();
+        warnings
+            .into_iter()
+            .map(|warning| {
+                assert_eq!(Severity::Warning, warning.severity);
+                (warning.line, warning.message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_diagnostics() {
+        let source = "var a = 1;\nprint a +;\n";
+        let diagnostics = compile(source).unwrap_err();
+
+        assert_eq!(
+            vec![Diagnostic {
+                severity: Severity::Error,
+                message: "Expected an expression".to_owned(),
+                span: 20..21,
+                line: 2,
+                column: 10,
+                note: None,
+            }],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn reports_nested_function_errors() {
+        let source = r#"
+fun outer() {
+    fun inner() {
+        var a = 1;
+        var a = 2;
+    }
+    return 1 +;
+}
+"#;
+        let diagnostics = compile(source).unwrap_err();
+
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .collect();
+
+        let messages: Vec<_> = errors
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    5,
+                    "Variable with this name already exists in the current scope"
+                ),
+                (7, "Expected an expression"),
+            ],
+            messages
+        );
+        assert_eq!(
+            Some("Previously declared on line 4"),
+            errors[0].note.as_deref()
+        );
+        assert_eq!("a", &source[errors[0].span.clone()]);
+    }
+
+    #[test]
+    fn reports_unexpected_end() {
+        let diagnostics = compile("print 1").unwrap_err();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("Expected a ';' after value", diagnostics[0].message);
+        assert_eq!(
+            Some("Reached the end of the source"),
+            diagnostics[0].note.as_deref()
+        );
+    }
+
+    #[test]
+    fn warns_about_unused_variables() {
+        let source = r#"
+fun add(a, b, _c) {
+    var unused = 1;
+    return a;
+}
+{
+    var used = add(1, 2, 3);
+    var assigned;
+    assigned = used;
+}
+"#;
+        assert_eq!(
+            vec![
+                (2, "Unused variable 'b'".to_owned()),
+                (3, "Unused variable 'unused'".to_owned()),
+                (8, "Unused variable 'assigned'".to_owned()),
+            ],
+            warnings(source)
+        );
+    }
+
+    #[test]
+    fn warns_about_unreachable_code() {
+        let source = r#"
+fun early(a) {
+    if (a) return 1;
+    return 2;
+    print a;
+    print a;
+}
+"#;
+        assert_eq!(vec![(5, "Unreachable code".to_owned())], warnings(source));
+    }
+
+    #[test]
+    fn warns_about_shadowing() {
+        let source = r#"
+var global = 1;
+fun shadow(global) {
+    {
+        var global = 2;
+        print global;
+    }
+    return global;
+}
+"#;
+        assert_eq!(
+            vec![
+                (3, "Variable 'global' shadows a global variable".to_owned()),
+                (5, "Variable 'global' shadows a local variable".to_owned()),
+            ],
+            warnings(source)
+        );
+    }
+
+    #[test]
+    fn warns_about_unused_values() {
+        let source = r#"
+var a = 1;
+1 + 2;
+a == 1 or a;
+a = 2;
+clock();
+"#;
+        let (_, diagnostics) = compile_with_warnings(source).unwrap();
+        assert_eq!(2, diagnostics.len());
+        assert_eq!("Expression result is unused", diagnostics[0].message);
+        assert_eq!("1 + 2", &source[diagnostics[0].span.clone()]);
+        assert_eq!("a == 1 or a", &source[diagnostics[1].span.clone()]);
+    }
+
+    #[test]
+    fn allow_pragma_silences_warnings() {
+        let source = r#"
+{
+    // lox:allow(unused_variable)
+    var a = 1;
+    var b = 2; // lox:allow(unused_variable, shadowing)
+    var c = 3; // lox:allow(shadowing)
+}
+"#;
+        assert_eq!(
+            vec![(6, "Unused variable 'c'".to_owned())],
+            warnings(source)
+        );
+    }
+
+    #[test]
+    fn allow_pragma_silences_each_warning() {
+        let source = r#"
+var g = 1;
+fun f(a) {
+    var g = 2; // lox:allow(shadowing)
+    1 + 2; // lox:allow(unused_value)
+    return a + g;
+    print 2; // lox:allow(unreachable_code)
+}
+{
+    var unused = 3; // lox:allow(unused_variable)
+}
+"#;
+        assert!(warnings(source).is_empty());
+
+        // Pragmas only silence the warnings they name
+        let source = r#"
+var g = 1;
+fun f(a) {
+    var g = 2; // lox:allow(unused_value)
+    1 + 2; // lox:allow(shadowing)
+    return a + g;
+    print 2; // lox:allow(unused_variable)
+}
+"#;
+        assert_eq!(
+            vec![
+                (4, "Variable 'g' shadows a global variable".to_owned()),
+                (5, "Expression result is unused".to_owned()),
+                (7, "Unreachable code".to_owned()),
+            ],
+            warnings(source)
+        );
+    }
+
+    #[test]
+    fn reuses_constants() {
+        let mut source = "var total = 0; var text = \"\";\n".to_owned();
+        for _ in 0..1000 {
+            source.push_str("total = total + 1; text = text + \"x\";\n");
+        }
+        source.push_str("fun f() {\n");
+        for _ in 0..1000 {
+            source.push_str("    total = total + 2;\n");
+        }
+        source.push_str("}\nf();\n");
+
+        let function = compile(&source).unwrap();
+        assert_eq!(5, function.chunk.constants.len());
+
+        let mut vm = Vm::new();
+        vm.interpret(function).unwrap();
+        assert_eq!(Some(Value::Number(3000.0)), vm.get_global("total"));
+        assert_eq!(
+            Some(1000),
+            vm.get_global("text")
+                .and_then(|text| text.as_str().map(str::len))
+        );
+    }
+
+    #[test]
+    fn long_operands() {
+        // 300 distinct constants, globals and locals, plus the variables declared below
+        let mut source = String::new();
+        for i in 0..300 {
+            source.push_str(&format!("var global{i} = {i};\n"));
+        }
+        source.push_str("fun sum() {\n    var total = 0;\n");
+        for i in 0..300 {
+            source.push_str(&format!("    var local{i} = global{i} + {};\n", i + 1000));
+        }
+        for i in 0..300 {
+            source.push_str(&format!("    total = total + local{i};\n"));
+        }
+        source.push_str("    return total;\n}\nvar result = sum();\nglobal299 = \"last\";\n");
+
+        let expected = (0..300).map(|i| 2 * i + 1000).sum::<i32>() as f64;
+        let run = |vm: &mut Vm, function| {
+            vm.interpret(function).unwrap();
+            assert_eq!(Some(Value::Number(expected)), vm.get_global("result"));
+            assert_eq!(Some(Value::new_string("last")), vm.get_global("global299"));
+        };
+
+        let function = compile(&source).unwrap();
+        run(&mut Vm::new(), function);
+
+        let mut vm = Vm::new();
+        let function = vm.compile(&source).unwrap();
+        run(&mut vm, function);
+    }
+
+    #[test]
+    fn long_jumps() {
+        // Each statement compiles to 8 bytes, so every body is longer than 64 KiB
+        let body = "    x = x + 1;\n".repeat(9000);
+        let source = format!(
+            "var x = 0;\nvar i = 0;\nif (x == 0) {{\n{body}}} else {{\n    x = -1;\n}}\n\
+             while (i < 2) {{\n    i = i + 1;\n{body}}}\n"
+        );
+
+        let mut vm = Vm::new();
+        let function = vm.compile(&source).unwrap();
+        let ops = op_codes(&function.chunk.code);
+        assert!(ops.contains(&OpCode::LongJumpIfFalse));
+        assert!(ops.contains(&OpCode::LongLoop));
+
+        vm.interpret(function).unwrap();
+        assert_eq!(Some(Value::Number(27000.0)), vm.get_global("x"));
+
+        // Jumps that fit are shortened
+        let function = compile("var a = 1; if (a) { a = 2; } while (a < 5) a = a + 1;").unwrap();
+        let ops = op_codes(&function.chunk.code);
+        assert!(ops.contains(&OpCode::JumpIfFalse) && ops.contains(&OpCode::Loop));
+        assert!(!ops.iter().any(|op| op.short_jump().is_some()));
+    }
+
+    #[test]
+    fn folds_constants() {
+        let cases = [
+            ("2 * 3 + 1", Value::Number(7.0)),
+            ("-5", Value::Number(-5.0)),
+            ("-(2 - 7)", Value::Number(5.0)),
+            ("\"foo\" + \"bar\"", Value::new_string("foobar")),
+            ("-\"abc\"", Value::new_string("cba")),
+            ("1 / 0", Value::Number(f64::INFINITY)),
+            ("7 % 3 == 1", Value::Boolean(true)),
+            ("1 != 2", Value::Boolean(true)),
+            ("2 >= 2", Value::Boolean(true)),
+            ("3 <= 2", Value::Boolean(false)),
+            ("0 / 0 >= 1", Value::Boolean(true)),
+            ("0 / 0 <= 1", Value::Boolean(true)),
+            ("0 / 0 > 1", Value::Boolean(false)),
+            ("!nil", Value::Boolean(true)),
+            ("nil and 1", Value::Nil),
+            ("1 and 2 + 3", Value::Number(5.0)),
+            ("false or \"x\"", Value::new_string("x")),
+            ("0 or 1", Value::Number(0.0)),
+        ];
+        for (expression, expected) in cases {
+            let mut vm = Vm::new();
+            let function = vm.compile(&format!("var result = {expression};")).unwrap();
+            let ops = op_codes(&function.chunk.code);
+            // The value is loaded by a single instruction before being stored
+            assert_eq!(
+                OpCode::DefineGlobal,
+                ops[1],
+                "{expression} compiled to {ops:?}"
+            );
+
+            vm.interpret(function).unwrap();
+            assert_eq!(Some(expected), vm.get_global("result"), "{expression}");
+        }
+
+        // Only the folded result is kept in the constants
+        let cases = [
+            ("1 + 2 + 3 + 4 + 5 + 6", vec![Value::Number(21.0)]),
+            ("\"a\" + \"b\" + \"c\"", vec![Value::new_string("abc")]),
+            ("nil and \"dead\" + \"code\"", vec![]),
+            ("1 or \"dead\"", vec![Value::Number(1.0)]),
+            ("\"x\" and 2 * 3", vec![Value::Number(6.0)]),
+            ("2 + 3 + 2", vec![Value::Number(7.0)]),
+        ];
+        for (expression, expected) in cases {
+            let function = compile(&format!("var result = {expression};")).unwrap();
+            assert_eq!(expected, function.chunk.constants, "{expression}");
+        }
+
+        // Operands that would fail are left to the VM so that it reports the error
+        for expression in ["\"a\" - 1", "-nil", "1 < \"b\""] {
+            let function = compile(&format!("var result = {expression};")).unwrap();
+            let error = Vm::new().interpret(function).unwrap_err();
+            assert_eq!(ErrorKind::Type, error.kind, "{expression}");
+        }
+
+        let function = compile("var a = 1; var b = a != 2 and a >= 1 and a <= 1;").unwrap();
+        let ops = op_codes(&function.chunk.code);
+        for op_code in [OpCode::NotEqual, OpCode::GreaterEqual, OpCode::LessEqual] {
+            assert!(ops.contains(&op_code));
+        }
+        assert!(!ops.contains(&OpCode::Not));
+
+        // `>=` and `<=` keep behaving like the negations of `<` and `>` for NaN at runtime
+        let mut vm = Vm::new();
+        let function = compile(
+            "var n = 0 / 0; var result = n >= 1 == !(n < 1) and n <= 1 == !(n > 1) and n >= n;",
+        )
+        .unwrap();
+        vm.interpret(function).unwrap();
+        assert_eq!(Some(Value::Boolean(true)), vm.get_global("result"));
+    }
+}
@@ -0,0 +1,351 @@
+//! Conversions between Rust and Lox values, used to register natives with typed parameters
+
+use crate::{
+    bigint::BigInt,
+    gc::Gc,
+    list::List,
+    object::{Arity, Object},
+    userdata::UserDataObject,
+    value::Value,
+    vm::{ErrorKind, VmError},
+};
+use std::ops::Deref;
+
+/// Conversion of a Rust value into a Lox value
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+/// Conversion of a Lox value into a Rust value
+pub trait FromLox: Sized {
+    /// Describes the accepted values in error messages, for example "a number"
+    fn expected() -> String;
+
+    fn from_lox(value: &Value) -> Option<Self>;
+}
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        self
+    }
+}
+
+impl FromLox for Value {
+    fn expected() -> String {
+        "any value".to_owned()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl FromLox for f64 {
+    fn expected() -> String {
+        "a number".to_owned()
+    }
+
+    /// Big integers are rounded to the nearest float
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(number) => Some(*number),
+            Value::Object(Object::BigInt(value)) => Some(value.to_f64()),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLox for i64 {
+    fn into_lox(self) -> Value {
+        BigInt::from_i64(self).into()
+    }
+}
+
+impl FromLox for i64 {
+    fn expected() -> String {
+        "an integer".to_owned()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        value.as_integer()?.to_i64()
+    }
+}
+
+impl IntoLox for BigInt {
+    fn into_lox(self) -> Value {
+        self.into()
+    }
+}
+
+impl FromLox for BigInt {
+    fn expected() -> String {
+        "an integer".to_owned()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        value.as_integer()
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromLox for bool {
+    fn expected() -> String {
+        "a boolean".to_owned()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl IntoLox for Gc<str> {
+    fn into_lox(self) -> Value {
+        Value::new_string(self)
+    }
+}
+
+impl FromLox for Gc<str> {
+    fn expected() -> String {
+        "a string".to_owned()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Object(Object::String(str)) => Some(str.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Value {
+        Value::new_string(self)
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Value {
+        Value::new_string(self)
+    }
+}
+
+impl FromLox for String {
+    fn expected() -> String {
+        "a string".to_owned()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        value.as_str().map(str::to_owned)
+    }
+}
+
+impl IntoLox for UserDataObject {
+    fn into_lox(self) -> Value {
+        Value::Object(Object::UserData(self))
+    }
+}
+
+impl FromLox for UserDataObject {
+    fn expected() -> String {
+        "a host object".to_owned()
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Object(Object::UserData(value)) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        Value::Nil
+    }
+}
+
+/// `None` is represented by nil
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        self.map_or(Value::Nil, T::into_lox)
+    }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+    fn expected() -> String {
+        format!("{} or nil", T::expected())
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        match value {
+            Value::Nil => Some(None),
+            value => T::from_lox(value).map(Some),
+        }
+    }
+}
+
+/// Lists are passed to scripts as a [`List`]
+impl<T: IntoLox> IntoLox for Vec<T> {
+    fn into_lox(self) -> Value {
+        let items = self.into_iter().map(T::into_lox).collect();
+        Value::new_userdata(List::new(items))
+    }
+}
+
+impl<T: FromLox> FromLox for Vec<T> {
+    fn expected() -> String {
+        format!("a list whose items are each {}", T::expected())
+    }
+
+    fn from_lox(value: &Value) -> Option<Self> {
+        let list = value.as_userdata::<List>()?;
+        list.to_vec().iter().map(T::from_lox).collect()
+    }
+}
+
+/// Tuples are passed to scripts as a [`List`] with one item per field
+macro_rules! impl_tuple {
+    ($($field:ident = $index:tt),+) => {
+        impl<$($field: IntoLox),+> IntoLox for ($($field,)+) {
+            fn into_lox(self) -> Value {
+                Value::new_userdata(List::new(vec![$(self.$index.into_lox()),+]))
+            }
+        }
+
+        impl<$($field: FromLox),+> FromLox for ($($field,)+) {
+            fn expected() -> String {
+                let fields: &[String] = &[$($field::expected()),+];
+                format!("a list of ({})", fields.join(", "))
+            }
+
+            fn from_lox(value: &Value) -> Option<Self> {
+                let items = value.as_userdata::<List>()?.to_vec();
+                let len = [$($index),+].len();
+                if items.len() != len {
+                    return None;
+                }
+                Some(($($field::from_lox(&items[$index])?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A = 0);
+impl_tuple!(A = 0, B = 1);
+impl_tuple!(A = 0, B = 1, C = 2);
+impl_tuple!(A = 0, B = 1, C = 2, D = 3);
+impl_tuple!(A = 0, B = 1, C = 2, D = 3, E = 4);
+impl_tuple!(A = 0, B = 1, C = 2, D = 3, E = 4, G = 5);
+
+/// Parameter of a typed native that takes any number of arguments of type `T`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variadic<T>(pub Vec<T>);
+
+impl<T> Deref for Variadic<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+/// Return value of a typed native, either a plain value or a `Result` for natives that can fail
+pub trait IntoLoxResult {
+    fn into_lox_result(self) -> Result<Value, VmError>;
+}
+
+impl<T: IntoLox> IntoLoxResult for T {
+    fn into_lox_result(self) -> Result<Value, VmError> {
+        Ok(self.into_lox())
+    }
+}
+
+impl<T: IntoLox> IntoLoxResult for Result<T, VmError> {
+    fn into_lox_result(self) -> Result<Value, VmError> {
+        self.map(T::into_lox)
+    }
+}
+
+/// Rust function that can be registered with
+/// [`Vm::define_typed_native`](crate::Vm::define_typed_native).
+///
+/// Implemented for functions with up to 6 parameters that implement [`FromLox`], and for
+/// variadic functions that take all arguments as a single [`Variadic<T>`].
+pub trait TypedNative<Args>: 'static {
+    fn arity() -> Arity;
+
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, VmError>;
+}
+
+/// Converts the argument at `index`, the arity has already been checked by the VM
+fn argument<T: FromLox>(name: &str, args: &[Value], index: usize) -> Result<T, VmError> {
+    let value = &args[index];
+    T::from_lox(value).ok_or_else(|| {
+        VmError::new(
+            ErrorKind::Type,
+            format!(
+                "{name} expected {} as argument {}, got {value}",
+                T::expected(),
+                index + 1
+            ),
+        )
+    })
+}
+
+macro_rules! impl_typed_native {
+    ($($arg:ident = $index:tt),*) => {
+        impl<F, R, $($arg,)*> TypedNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoLoxResult,
+            $($arg: FromLox,)*
+        {
+            fn arity() -> Arity {
+                let count: &[usize] = &[$($index),*];
+                Arity::from(count.len() as u8)
+            }
+
+            #[allow(unused_variables)]
+            fn call(&self, name: &str, args: &[Value]) -> Result<Value, VmError> {
+                self($(argument::<$arg>(name, args, $index)?),*).into_lox_result()
+            }
+        }
+    };
+}
+
+impl_typed_native!();
+impl_typed_native!(A = 0);
+impl_typed_native!(A = 0, B = 1);
+impl_typed_native!(A = 0, B = 1, C = 2);
+impl_typed_native!(A = 0, B = 1, C = 2, D = 3);
+impl_typed_native!(A = 0, B = 1, C = 2, D = 3, E = 4);
+impl_typed_native!(A = 0, B = 1, C = 2, D = 3, E = 4, G = 5);
+
+impl<F, R, T> TypedNative<Variadic<T>> for F
+where
+    F: Fn(Variadic<T>) -> R + 'static,
+    R: IntoLoxResult,
+    T: FromLox,
+{
+    fn arity() -> Arity {
+        Arity::from(0..)
+    }
+
+    fn call(&self, name: &str, args: &[Value]) -> Result<Value, VmError> {
+        let args = (0..args.len())
+            .map(|index| argument(name, args, index))
+            .collect::<Result<_, _>>()?;
+        self(Variadic(args)).into_lox_result()
+    }
+}
@@ -0,0 +1,147 @@
+use std::{fmt, ops::Range};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Problem found in the source code during compilation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Byte range of the offending code in the source
+    pub span: Range<usize>,
+    pub line: u32,
+    pub column: u32,
+    pub note: Option<String>,
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic with the offending source line and its span underlined
+    pub fn render(&self, source: &str) -> String {
+        let mut output = format!("{}: {}\n", self.severity, self.message);
+        output.push_str(&render_snippet(source, self.line, self.column, &self.span));
+
+        if let Some(note) = &self.note {
+            let gutter = " ".repeat(self.line.to_string().len());
+            output.push_str(&format!("\n{gutter} = note: {note}"));
+        }
+
+        output
+    }
+}
+
+/// Formats the location of `span` and the source line containing it, with the span underlined
+/// in the style of rustc
+pub(crate) fn render_snippet(source: &str, line: u32, column: u32, span: &Range<usize>) -> String {
+    let gutter = " ".repeat(line.to_string().len());
+    let mut output = format!("{gutter}--> line {line}, column {column}");
+
+    if span.start > source.len() {
+        return output;
+    }
+
+    let mut start = span.start;
+    while !source.is_char_boundary(start) {
+        start -= 1;
+    }
+
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+
+    // Spans over multiple lines are only underlined until the end of the first one
+    let mut end = span.end.clamp(start, line_end);
+    while !source.is_char_boundary(end) {
+        end += 1;
+    }
+
+    // Tabs are kept so that the underline lines up with the code above it
+    let padding: String = source[line_start..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let underline = "^".repeat(source[start..end].chars().count().max(1));
+
+    let text = source[line_start..line_end].trim_end_matches('\r');
+    output.push_str(&format!(
+        "\n{gutter} |\n{line} | {text}\n{gutter} | {padding}{underline}"
+    ));
+    output
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[line {}, column {}] {}: {}",
+            self.line, self.column, self.severity, self.message
+        )?;
+        if let Some(note) = &self.note {
+            write!(f, "\nnote: {note}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Severity};
+
+    #[test]
+    fn render_snippet() {
+        let source = "var a = 1;\n\tprint a +;\n";
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            message: "Expected an expression".to_owned(),
+            span: 21..22,
+            line: 2,
+            column: 11,
+            note: Some("Operators need two operands".to_owned()),
+        };
+
+        assert_eq!(
+            "error: Expected an expression
+ --> line 2, column 11
+  |
+2 | \tprint a +;
+  | \t         ^
+  = note: Operators need two operands",
+            diagnostic.render(source)
+        );
+    }
+
+    #[test]
+    fn render_snippet_at_end() {
+        let source = "print \"unterminated\nstring";
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            message: "Unterminated string literal".to_owned(),
+            span: 6..source.len(),
+            line: 1,
+            column: 7,
+            note: None,
+        };
+
+        assert_eq!(
+            "error: Unterminated string literal
+ --> line 1, column 7
+  |
+1 | print \"unterminated
+  |       ^^^^^^^^^^^^^",
+            diagnostic.render(source)
+        );
+    }
+}
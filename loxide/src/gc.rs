@@ -0,0 +1,315 @@
+//! Handles to the objects that scripts work with, which are owned by the heap of a VM
+
+use std::{
+    alloc::{self, Layout},
+    borrow::Borrow,
+    cell::Cell,
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::Deref,
+    ptr::{self, NonNull},
+    rc::Rc,
+};
+
+/// Bookkeeping stored in front of every object
+pub(crate) struct GcHeader {
+    /// Number of handles to the object
+    handles: Cell<usize>,
+    /// Id of the VM whose heap owns the object, 0 while it is owned by its handles
+    owner: Cell<u64>,
+    pub(crate) marked: Cell<bool>,
+    /// Number of handles held by other objects of the heap, counted during collections
+    pub(crate) internal: Cell<usize>,
+    /// Bytes charged to the heap for the object, 0 until it is tracked
+    pub(crate) size: Cell<usize>,
+}
+
+impl GcHeader {
+    fn new() -> Self {
+        Self {
+            handles: Cell::new(1),
+            owner: Cell::new(0),
+            marked: Cell::new(false),
+            internal: Cell::new(0),
+            size: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn handles(&self) -> usize {
+        self.handles.get()
+    }
+
+    pub(crate) fn owner(&self) -> u64 {
+        self.owner.get()
+    }
+
+    /// Hands the object to the heap of a VM, or back to its handles with 0. Objects owned by a
+    /// heap are only freed by it.
+    pub(crate) fn set_owner(&self, owner: u64) {
+        self.owner.set(owner);
+    }
+}
+
+#[repr(C)]
+pub(crate) struct GcBox<T: ?Sized> {
+    pub(crate) header: GcHeader,
+    pub(crate) value: T,
+}
+
+impl<T> GcBox<T> {
+    pub(crate) fn new(value: T) -> Box<Self> {
+        Box::new(Self {
+            header: GcHeader::new(),
+            value,
+        })
+    }
+}
+
+/// Handle to an object, cloning it creates another handle to the same object.
+///
+/// Objects are owned by the heap of the VM that they were passed to, and are freed by its garbage
+/// collector once they are unreachable and have no handles left, so the handles that the host
+/// keeps stay valid. Objects that haven't reached a VM yet, or whose VM has been dropped, are
+/// freed with their last handle.
+pub struct Gc<T: ?Sized> {
+    ptr: NonNull<GcBox<T>>,
+    marker: PhantomData<GcBox<T>>,
+}
+
+impl<T> Gc<T> {
+    pub fn new(value: T) -> Self {
+        Self::from_box(GcBox::new(value))
+    }
+}
+
+impl<T: ?Sized> Gc<T> {
+    /// Takes ownership of an object allocated with [`GcBox::new`], which can be unsized first
+    pub(crate) fn from_box(boxed: Box<GcBox<T>>) -> Self {
+        Self {
+            ptr: NonNull::from(Box::leak(boxed)),
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates another handle to an object that the heap refers to without one
+    ///
+    /// # Safety
+    /// The object must still be alive.
+    pub(crate) unsafe fn from_ptr(ptr: NonNull<GcBox<T>>) -> Self {
+        let header = &ptr.as_ref().header;
+        header.handles.set(header.handles.get() + 1);
+        Self {
+            ptr,
+            marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn as_ptr(this: &Self) -> NonNull<GcBox<T>> {
+        this.ptr
+    }
+
+    pub(crate) fn header(this: &Self) -> &GcHeader {
+        // SAFETY: the handle keeps the object alive
+        unsafe { &this.ptr.as_ref().header }
+    }
+
+    /// Checks if two handles refer to the same object
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        ptr::addr_eq(a.ptr.as_ptr(), b.ptr.as_ptr())
+    }
+
+    /// Frees an object that no handle refers to anymore
+    ///
+    /// # Safety
+    /// The object must be alive and have no handles.
+    pub(crate) unsafe fn free(ptr: NonNull<GcBox<T>>) {
+        debug_assert_eq!(0, ptr.as_ref().header.handles());
+        drop(Box::from_raw(ptr.as_ptr()));
+    }
+}
+
+impl<T: Clone> Gc<T> {
+    /// Returns a mutable reference to the object, copying it first if there are other handles
+    /// to it
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        if Gc::header(this).handles() != 1 {
+            *this = Gc::new(T::clone(this));
+        }
+        // SAFETY: this is the only handle, and the heap doesn't hold references to the objects
+        // it owns outside of collections
+        unsafe { &mut this.ptr.as_mut().value }
+    }
+}
+
+impl<T: ?Sized> Deref for Gc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the handle keeps the object alive
+        unsafe { &self.ptr.as_ref().value }
+    }
+}
+
+impl<T: ?Sized> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        // SAFETY: the handle keeps the object alive
+        unsafe { Self::from_ptr(self.ptr) }
+    }
+}
+
+impl<T: ?Sized> Drop for Gc<T> {
+    fn drop(&mut self) {
+        let header = Gc::header(self);
+        let handles = header.handles() - 1;
+        header.handles.set(handles);
+        if handles == 0 && header.owner() == 0 {
+            // SAFETY: this was the last handle, and no heap owns the object
+            unsafe { Gc::free(self.ptr) }
+        }
+    }
+}
+
+impl From<&str> for Gc<str> {
+    fn from(value: &str) -> Self {
+        let (layout, offset) = Layout::new::<GcHeader>()
+            .extend(Layout::for_value(value))
+            .expect("String is too long");
+        let layout = layout.pad_to_align();
+
+        // SAFETY: the layout is the one of `GcBox<str>` with `value.len()` bytes, so that
+        // `Box` can free it
+        unsafe {
+            let memory = alloc::alloc(layout);
+            if memory.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+            ptr::copy_nonoverlapping(value.as_ptr(), memory.add(offset), value.len());
+            let ptr = ptr::slice_from_raw_parts_mut(memory, value.len()) as *mut GcBox<str>;
+            ptr::addr_of_mut!((*ptr).header).write(GcHeader::new());
+            Self {
+                ptr: NonNull::new_unchecked(ptr),
+                marker: PhantomData,
+            }
+        }
+    }
+}
+
+impl From<String> for Gc<str> {
+    fn from(value: String) -> Self {
+        value.as_str().into()
+    }
+}
+
+impl From<&String> for Gc<str> {
+    fn from(value: &String) -> Self {
+        value.as_str().into()
+    }
+}
+
+impl From<Rc<str>> for Gc<str> {
+    fn from(value: Rc<str>) -> Self {
+        (*value).into()
+    }
+}
+
+impl From<Gc<str>> for Rc<str> {
+    fn from(value: Gc<str>) -> Self {
+        (*value).into()
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Gc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for Gc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for Gc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Gc<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for Gc<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for Gc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Gc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Gc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Gc;
+    use std::{cell::Cell, rc::Rc};
+
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn freed_with_the_last_handle() {
+        let dropped = Rc::new(Cell::new(0));
+        let a = Gc::new(Counted(dropped.clone()));
+        let b = a.clone();
+        assert!(Gc::ptr_eq(&a, &b));
+
+        drop(a);
+        assert_eq!(0, dropped.get());
+        drop(b);
+        assert_eq!(1, dropped.get());
+    }
+
+    #[test]
+    fn strings() {
+        let empty = Gc::<str>::from("");
+        assert_eq!("", &*empty);
+
+        let text = Gc::<str>::from(String::from("héllo"));
+        let copy = text.clone();
+        assert_eq!("héllo", &*copy);
+        assert_eq!(Gc::from("héllo"), text);
+        assert!(!Gc::ptr_eq(&Gc::from("héllo"), &text));
+    }
+
+    #[test]
+    fn make_mut() {
+        let mut a = Gc::new(vec![1]);
+        Gc::make_mut(&mut a).push(2);
+
+        let b = a.clone();
+        Gc::make_mut(&mut a).push(3);
+        assert_eq!(vec![1, 2, 3], *a);
+        assert_eq!(vec![1, 2], *b);
+    }
+}
@@ -0,0 +1,45 @@
+//! Bytecode virtual machine for the [Lox](https://craftinginterpreters.com) programming language.
+//!
+//! ```
+//! use loxide::{compile, Value, Vm};
+//!
+//! let mut vm = Vm::new();
+//! vm.set_global("base", Value::Number(40.0));
+//!
+//! let function = compile("var answer = base + 2;").unwrap();
+//! vm.interpret(function).unwrap();
+//!
+//! assert_eq!(Some(Value::Number(42.0)), vm.get_global("answer"));
+//! ```
+
+mod bigint;
+mod chunk;
+#[macro_use]
+mod macros;
+mod compiler;
+mod convert;
+mod diagnostic;
+mod gc;
+mod list;
+mod object;
+mod op_code;
+mod scanner;
+mod userdata;
+mod value;
+mod vm;
+
+pub use crate::{
+    bigint::{BigInt, Radix},
+    compiler::{compile, compile_with_warnings},
+    convert::{FromLox, IntoLox, IntoLoxResult, TypedNative, Variadic},
+    diagnostic::{Diagnostic, Severity},
+    gc::Gc,
+    list::List,
+    object::{Arity, FunctionObject, NativeFunction, Object},
+    userdata::{Method, MethodFn, UserData, UserDataObject},
+    value::{ArithmeticOp, ComparisonOp, Value},
+    vm::{
+        CaptureBuffer, ErrorKind, InterpretResult, InterruptHandle, NativeContext, NativeFn,
+        TraceFrame, Vm, VmConfig, VmError,
+    },
+};
@@ -0,0 +1,142 @@
+//! List of values that the host passes to scripts, created by converting a `Vec` or a tuple
+
+use crate::{
+    object::Arity,
+    userdata::{Method, UserData},
+    value::Value,
+    vm::{ErrorKind, NativeContext, VmError},
+};
+use std::{cell::RefCell, fmt, mem};
+
+/// Host object holding a list of values.
+/// Scripts can call `len()`, `get(index)`, `set(index, value)` and `push(value)` on it.
+#[derive(Debug, Default)]
+pub struct List {
+    items: RefCell<Vec<Value>>,
+}
+
+impl List {
+    pub fn new(items: Vec<Value>) -> Self {
+        Self {
+            items: RefCell::new(items),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.borrow().is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.items.borrow().get(index).cloned()
+    }
+
+    pub fn push(&self, value: Value) {
+        self.items.borrow_mut().push(value);
+    }
+
+    /// Copies the items out of the list
+    pub fn to_vec(&self) -> Vec<Value> {
+        self.items.borrow().clone()
+    }
+
+    fn index(&self, value: &Value) -> Result<usize, VmError> {
+        let len = self.len();
+        match value.as_number() {
+            Some(index) if index.fract() == 0.0 && index >= 0.0 && index < len as f64 => {
+                Ok(index as usize)
+            }
+            Some(index) if index.fract() == 0.0 => Err(VmError::new(
+                ErrorKind::Native,
+                format!("List index {index} is out of range for length {len}"),
+            )),
+            _ => Err(VmError::new(
+                ErrorKind::Type,
+                format!("List index must be an integer, got {value}"),
+            )),
+        }
+    }
+
+    fn script_len(&self, _ctx: &mut NativeContext, _args: &[Value]) -> Result<Value, VmError> {
+        Ok(Value::Number(self.len() as f64))
+    }
+
+    fn script_get(&self, _ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+        let index = self.index(&args[0])?;
+        Ok(self.items.borrow()[index].clone())
+    }
+
+    fn script_set(&self, _ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+        let index = self.index(&args[0])?;
+        self.items.borrow_mut()[index] = args[1].clone();
+        Ok(Value::Nil)
+    }
+
+    fn script_push(&self, _ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+        self.push(args[0].clone());
+        Ok(Value::Nil)
+    }
+}
+
+impl UserData for List {
+    const METHODS: &'static [Method<Self>] = &[
+        Method {
+            name: "len",
+            arity: Arity { min: 0, max: 0 },
+            function: List::script_len,
+        },
+        Method {
+            name: "get",
+            arity: Arity { min: 1, max: 1 },
+            function: List::script_get,
+        },
+        Method {
+            name: "set",
+            arity: Arity { min: 2, max: 2 },
+            function: List::script_set,
+        },
+        Method {
+            name: "push",
+            arity: Arity { min: 1, max: 1 },
+            function: List::script_push,
+        },
+    ];
+
+    fn type_name(&self) -> &str {
+        "List"
+    }
+
+    fn trace(&self, visit: &mut dyn FnMut(&Value)) {
+        self.items.borrow().iter().for_each(visit);
+    }
+
+    fn clear(&self) {
+        self.items.borrow_mut().clear();
+    }
+
+    fn heap_size(&self) -> usize {
+        self.items.borrow().capacity() * mem::size_of::<Value>()
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The list is borrowed mutably while it's printed, so that a list containing itself
+        // prints the inner reference as `[...]` instead of recursing forever
+        let Ok(items) = self.items.try_borrow_mut() else {
+            return f.write_str("[...]");
+        };
+
+        f.write_str("[")?;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            item.fmt(f)?;
+        }
+        f.write_str("]")
+    }
+}
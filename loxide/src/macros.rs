@@ -1,4 +1,3 @@
-#[macro_export]
 macro_rules! convertable_enum {
     ($enum:ident, $($name:ident = $value:literal,)+) => {
         #[derive(Debug, Clone, Copy)]
@@ -47,7 +46,7 @@ macro_rules! convertable_enum {
         use std::cmp;
         impl PartialOrd for $enum {
             fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-                Some(self.as_byte().cmp(&other.as_byte()))
+                Some(self.cmp(other))
             }
         }
 
@@ -1,14 +1,4 @@
-mod chunk;
-#[macro_use]
-mod macros;
-mod compiler;
-mod object;
-mod op_code;
-mod scanner;
-mod value;
-mod vm;
-
-use crate::{compiler::compile, vm::Vm};
+use loxide::{Diagnostic, Vm};
 use std::{
     env, fs,
     io::{stdin, stdout, Write},
@@ -26,13 +16,15 @@ fn main() {
 
 fn run_file(path: &str) {
     let source = fs::read_to_string(path).unwrap();
-    if let Some(function) = compile(&source) {
-        let mut vm = Vm::new();
-        if let Err(err) = vm.interpret(function) {
-            eprintln!("VM error: {err:?}");
+    let mut vm = Vm::new();
+    match vm.compile_with_warnings(&source) {
+        Ok((function, warnings)) => {
+            report_diagnostics(&mut vm, &source, &warnings);
+            if let Err(err) = vm.interpret(function) {
+                vm.report_error(&err, &source).unwrap();
+            }
         }
-    } else {
-        eprintln!("Could not compile");
+        Err(diagnostics) => report_diagnostics(&mut vm, &source, &diagnostics),
     }
 }
 
@@ -42,21 +34,61 @@ fn repl() {
     stdout.flush().unwrap();
 
     let mut vm = Vm::new();
+    sigint::forward_to(vm.interrupt_handle());
 
     for line in stdin().lines() {
         let line = line.unwrap();
-        match compile(&line) {
-            Some(function) => {
+        match vm.compile(&line) {
+            Ok(function) => {
                 if let Err(err) = vm.interpret(function) {
-                    eprintln!("VM error: {err:?}");
+                    vm.report_error(&err, &line).unwrap();
                 }
             }
-            None => {
-                eprintln!("Could not compile");
-            }
+            Err(diagnostics) => report_diagnostics(&mut vm, &line, &diagnostics),
         }
 
         print!("> ");
         stdout.flush().unwrap();
     }
 }
+
+fn report_diagnostics(vm: &mut Vm, source: &str, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        vm.report_diagnostic(diagnostic, source).unwrap();
+    }
+}
+
+/// Ctrl-C stops the running script instead of killing the REPL
+#[cfg(unix)]
+mod sigint {
+    use loxide::InterruptHandle;
+    use std::{ffi::c_int, sync::OnceLock};
+
+    const SIGINT: c_int = 2;
+
+    static HANDLE: OnceLock<InterruptHandle> = OnceLock::new();
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn on_sigint(_signum: c_int) {
+        if let Some(handle) = HANDLE.get() {
+            handle.interrupt();
+        }
+    }
+
+    pub fn forward_to(handle: InterruptHandle) {
+        if HANDLE.set(handle).is_ok() {
+            // SAFETY: the handler only does an atomic store
+            unsafe {
+                signal(SIGINT, on_sigint);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sigint {
+    pub fn forward_to(_handle: loxide::InterruptHandle) {}
+}
@@ -1,11 +1,33 @@
-use crate::{chunk::Chunk, value::Value};
-use std::{fmt, rc::Rc};
+use crate::{bigint::BigInt, chunk::Chunk, gc::Gc, userdata::UserDataObject, vm::NativeFn};
+use std::{
+    fmt,
+    ops::{RangeFrom, RangeInclusive},
+    rc::Rc,
+};
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum Object {
-    String(Rc<str>),
-    Function(FunctionObject),
-    NativeFunction(fn(&[Value]) -> Value),
+    String(Gc<str>),
+    BigInt(Gc<BigInt>),
+    Function(Gc<FunctionObject>),
+    NativeFunction(Gc<NativeFunction>),
+    UserData(UserDataObject),
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            // The VM compares its strings with `Value::equals_interned` instead, they are all
+            // interned. Strings created by the host are only interned once they're passed to a
+            // VM, so this also has to compare the contents.
+            (Object::String(a), Object::String(b)) => Gc::ptr_eq(a, b) || a == b,
+            (Object::BigInt(a), Object::BigInt(b)) => a == b,
+            (Object::Function(a), Object::Function(b)) => Gc::ptr_eq(a, b),
+            (Object::NativeFunction(a), Object::NativeFunction(b)) => Gc::ptr_eq(a, b),
+            (Object::UserData(a), Object::UserData(b)) => a == b,
+            _ => false,
+        }
+    }
 }
 
 #[derive(PartialEq, Clone)]
@@ -13,6 +35,11 @@ pub struct FunctionObject {
     pub arity: u8,
     pub chunk: Chunk,
     pub name: Rc<str>,
+    /// Names of the global slots used by a script that was compiled without a VM, the VM that
+    /// runs it maps them to its own slots. `None` once the script belongs to a VM.
+    pub(crate) unlinked_globals: Option<Vec<Rc<str>>>,
+    /// Id of the VM whose global slots the code refers to, 0 if it isn't tied to a VM
+    pub(crate) vm_id: u64,
 }
 
 impl Default for FunctionObject {
@@ -21,6 +48,8 @@ impl Default for FunctionObject {
             arity: Default::default(),
             chunk: Default::default(),
             name: "<placeholder>".into(),
+            unlinked_globals: None,
+            vm_id: 0,
         }
     }
 }
@@ -34,10 +63,85 @@ impl fmt::Debug for FunctionObject {
     }
 }
 
+/// Host function registered with [`Vm::define_native`](crate::Vm::define_native)
+pub struct NativeFunction {
+    pub name: Rc<str>,
+    pub arity: Arity,
+    pub function: Box<NativeFn>,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+/// Number of arguments accepted by a native function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl Arity {
+    pub fn accepts(&self, arg_count: u8) -> bool {
+        (self.min..=self.max).contains(&arg_count)
+    }
+}
+
+impl From<u8> for Arity {
+    fn from(count: u8) -> Self {
+        Self {
+            min: count,
+            max: count,
+        }
+    }
+}
+
+impl From<RangeInclusive<u8>> for Arity {
+    fn from(range: RangeInclusive<u8>) -> Self {
+        Self {
+            min: *range.start(),
+            max: *range.end(),
+        }
+    }
+}
+
+impl From<RangeFrom<u8>> for Arity {
+    fn from(range: RangeFrom<u8>) -> Self {
+        Self {
+            min: range.start,
+            max: u8::MAX,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else if self.max == u8::MAX {
+            write!(f, "at least {}", self.min)
+        } else {
+            write!(f, "{} to {}", self.min, self.max)
+        }
+    }
+}
+
 impl_enum_conversions! {
     Object,
-    String, Rc<str>,
-    Function, FunctionObject,
+    String, Gc<str>,
+    Function, Gc<FunctionObject>,
+    UserData, UserDataObject,
+}
+
+impl From<FunctionObject> for Object {
+    fn from(value: FunctionObject) -> Self {
+        Object::Function(Gc::new(value))
+    }
 }
 
 impl From<&str> for Object {
@@ -50,8 +154,10 @@ impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Object::String(s) => s.fmt(f),
+            Object::BigInt(value) => value.fmt(f),
             Object::Function(funct) => write!(f, "<fun {}>", funct.name),
-            Object::NativeFunction(_) => write!(f, "<native fun>"),
+            Object::NativeFunction(native) => write!(f, "<native fun {}>", native.name),
+            Object::UserData(value) => value.fmt(f),
         }
     }
 }
@@ -26,4 +26,87 @@ convertable_enum! {
     Jump = 23,
     Loop = 24,
     Call = 25,
+    Modulo = 26,
+    TailCall = 27,
+    Invoke = 28,
+    LongDefineGlobal = 29,
+    LongGetGlobal = 30,
+    LongSetGlobal = 31,
+    LongGetLocal = 32,
+    LongSetLocal = 33,
+    LongInvoke = 34,
+    LongJump = 35,
+    LongJumpIfFalse = 36,
+    LongLoop = 37,
+    NotEqual = 38,
+    GreaterEqual = 39,
+    LessEqual = 40,
+}
+
+impl OpCode {
+    /// Largest index that fits in the operand of the long instructions
+    pub const MAX_LONG_INDEX: usize = (1 << 24) - 1;
+
+    /// Number of operand bytes that follow the instruction
+    pub fn operand_len(&self) -> usize {
+        use OpCode::*;
+        match self {
+            LongInvoke | LongJump | LongJumpIfFalse | LongLoop => 4,
+            LongConstant | LongDefineGlobal | LongGetGlobal | LongSetGlobal | LongGetLocal
+            | LongSetLocal => 3,
+            Jump | JumpIfFalse | Loop | Invoke => 2,
+            Constant | DefineGlobal | GetGlobal | SetGlobal | GetLocal | SetLocal | Call
+            | TailCall => 1,
+            _ => 0,
+        }
+    }
+
+    /// Version of the instruction with a 24 bit index instead of a one byte one, or with a 32 bit
+    /// jump distance instead of a 16 bit one
+    pub fn long_form(&self) -> Option<OpCode> {
+        use OpCode::*;
+        match self {
+            Constant => Some(LongConstant),
+            DefineGlobal => Some(LongDefineGlobal),
+            GetGlobal => Some(LongGetGlobal),
+            SetGlobal => Some(LongSetGlobal),
+            GetLocal => Some(LongGetLocal),
+            SetLocal => Some(LongSetLocal),
+            Invoke => Some(LongInvoke),
+            Jump => Some(LongJump),
+            JumpIfFalse => Some(LongJumpIfFalse),
+            Loop => Some(LongLoop),
+            _ => None,
+        }
+    }
+
+    /// Version of a long jump with a 16 bit distance
+    pub fn short_jump(&self) -> Option<OpCode> {
+        use OpCode::*;
+        match self {
+            LongJump => Some(Jump),
+            LongJumpIfFalse => Some(JumpIfFalse),
+            LongLoop => Some(Loop),
+            _ => None,
+        }
+    }
+
+    /// Checks if this is the long form of an instruction, whose index operand is 24 bits long
+    /// or whose jump distance is 32 bits long
+    pub fn is_long(&self) -> bool {
+        use OpCode::*;
+        matches!(
+            self,
+            LongConstant
+                | LongDefineGlobal
+                | LongGetGlobal
+                | LongSetGlobal
+                | LongGetLocal
+                | LongSetLocal
+                | LongInvoke
+                | LongJump
+                | LongJumpIfFalse
+                | LongLoop
+        )
+    }
 }
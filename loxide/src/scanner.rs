@@ -4,6 +4,16 @@ pub struct Scanner<'a> {
     start: usize,
     current: usize,
     line: u32,
+    // Offset of the first character on the current line
+    line_start: usize,
+    start_line: u32,
+    /// Column of `start`, counted in characters
+    start_column: u32,
+    /// Offset of the previous token on the current line, columns are counted from there
+    /// instead of from the start of the line
+    column_offset: usize,
+    /// Warnings silenced with `// lox:allow(...)` comments and the lines they apply to
+    pub allowed_warnings: Vec<(u32, &'a str)>,
 }
 
 impl<'a> Scanner<'a> {
@@ -13,6 +23,11 @@ impl<'a> Scanner<'a> {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            column_offset: 0,
+            allowed_warnings: Vec::new(),
         }
     }
 
@@ -20,6 +35,8 @@ impl<'a> Scanner<'a> {
         self.skip_whitespace();
 
         self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column_at(self.start);
 
         if self.is_at_end() {
             Ok(self.make_token(TokenType::Eof))
@@ -38,6 +55,7 @@ impl<'a> Scanner<'a> {
                 '+' => TokenType::Plus,
                 '/' => TokenType::Slash,
                 '*' => TokenType::Star,
+                '%' => TokenType::Percent,
                 '!' => {
                     if self.current_matches('=') {
                         TokenType::BangEqual
@@ -81,6 +99,7 @@ impl<'a> Scanner<'a> {
         while !self.is_at_end() && self.peek() != '"' {
             if self.peek() == '\n' {
                 self.line += 1;
+                self.line_start = self.current + 1;
             }
 
             self.current += 1;
@@ -172,14 +191,16 @@ impl<'a> Scanner<'a> {
             token_type,
             start: self.start,
             end: self.current,
-            line: self.line,
+            line: self.start_line,
+            column: self.start_column,
         }
     }
 
     fn error(&self, message: String) -> ScannerError {
         ScannerError {
             message,
-            line: self.line,
+            line: self.start_line,
+            column: self.start_column,
             start: self.start,
             end: self.current,
         }
@@ -195,6 +216,21 @@ impl<'a> Scanner<'a> {
         c
     }
 
+    fn column_at(&mut self, offset: usize) -> u32 {
+        if self.column_offset < self.line_start {
+            self.column_offset = self.line_start;
+            self.start_column = 1;
+        }
+
+        // UTF-8 continuation bytes don't start a new character
+        let chars = self.source.as_bytes()[self.column_offset..offset]
+            .iter()
+            .filter(|byte| (**byte as i8) >= -0x40)
+            .count();
+        self.column_offset = offset;
+        self.start_column + chars as u32
+    }
+
     fn peek(&self) -> char {
         // TODO: handle non-ascii
         *self.source.as_bytes().get(self.current).unwrap_or(&b'\0') as char
@@ -220,12 +256,15 @@ impl<'a> Scanner<'a> {
                 '\n' => {
                     self.line += 1;
                     self.current += 1;
+                    self.line_start = self.current;
                 }
                 '/' => {
                     if self.peek_next() == '/' {
+                        let comment_start = self.current + 2;
                         while !self.is_at_end() && self.peek() != '\n' {
                             self.current += 1;
                         }
+                        self.scan_pragma(comment_start);
                     } else {
                         break;
                     }
@@ -235,6 +274,25 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    fn scan_pragma(&mut self, comment_start: usize) {
+        let comment = self.source[comment_start..self.current].trim();
+
+        if let Some(warnings) = comment
+            .strip_prefix("lox:allow(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            // A comment on its own line applies to the next line, otherwise to the line it ends
+            let own_line = self.source[self.line_start..comment_start - 2]
+                .trim()
+                .is_empty();
+            let line = if own_line { self.line + 1 } else { self.line };
+
+            for warning in warnings.split(',') {
+                self.allowed_warnings.push((line, warning.trim()));
+            }
+        }
+    }
+
     fn current_matches(&mut self, expected: char) -> bool {
         if self.is_at_end() {
             return false;
@@ -246,7 +304,7 @@ impl<'a> Scanner<'a> {
         true
     }
 
-    pub fn lexeme(&self, token: Token) -> &str {
+    pub fn lexeme(&self, token: Token) -> &'a str {
         &self.source[token.start..token.end]
     }
 }
@@ -257,6 +315,7 @@ pub struct Token {
     pub start: usize,
     pub end: usize,
     pub line: u32,
+    pub column: u32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -272,6 +331,7 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
 
     Bang,
     BangEqual,
@@ -310,6 +370,7 @@ pub enum TokenType {
 pub struct ScannerError {
     pub message: String,
     pub line: u32,
+    pub column: u32,
     pub start: usize,
     pub end: usize,
 }
@@ -376,4 +437,46 @@ for (var i = 1; i <= 10; i = i + 1) {
 
         assert_eq!(expected_token_types, token_types);
     }
+
+    #[test]
+    fn scan_pragmas() {
+        let mut scanner = Scanner::new(
+            "// lox:allow(shadowing)\nvar a; // lox:allow(unused_variable, unused_value)\n// allow(x)",
+        );
+        while scanner.next_token().unwrap().token_type != TokenType::Eof {}
+
+        assert_eq!(
+            vec![
+                (2, "shadowing"),
+                (2, "unused_variable"),
+                (2, "unused_value")
+            ],
+            scanner.allowed_warnings
+        );
+    }
+
+    #[test]
+    fn token_positions() {
+        let mut scanner = Scanner::new("var a;\n  print \"multi\nline\" + a;");
+
+        let positions: Vec<_> = std::iter::from_fn(|| {
+            let token = scanner.next_token().unwrap();
+            (token.token_type != TokenType::Eof).then_some((token.line, token.column))
+        })
+        .collect();
+
+        assert_eq!(
+            vec![
+                (1, 1),
+                (1, 5),
+                (1, 6),
+                (2, 3),
+                (2, 9),
+                (3, 7),
+                (3, 9),
+                (3, 10)
+            ],
+            positions
+        );
+    }
 }
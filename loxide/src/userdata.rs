@@ -0,0 +1,177 @@
+//! Host objects that scripts can hold and call methods on, but not create or inspect
+
+use crate::{
+    gc::{Gc, GcBox},
+    object::Arity,
+    value::Value,
+    vm::{ErrorKind, NativeContext, VmError},
+};
+use std::{any::Any, fmt, mem};
+
+/// Method of a host object, called with the receiver and the arguments
+pub type MethodFn<T> = fn(&T, &mut NativeContext, &[Value]) -> Result<Value, VmError>;
+
+pub struct Method<T> {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub function: MethodFn<T>,
+}
+
+/// Rust value that can be passed to scripts, such as a file or a game entity.
+/// Scripts can only print it, compare it and call the methods in [`UserData::METHODS`].
+///
+/// Methods receive a shared reference, so state that they change needs interior mutability.
+/// Objects that store Lox values should implement [`UserData::trace`] and [`UserData::clear`],
+/// so that the garbage collector can free them when they form reference cycles.
+pub trait UserData: fmt::Display + Sized + 'static {
+    /// Methods callable from scripts as `value.name(args)`
+    const METHODS: &'static [Method<Self>];
+
+    fn type_name(&self) -> &str;
+
+    /// Compares two objects of the same type, by default an object is only equal to itself
+    fn equals(&self, _other: &Self) -> bool {
+        false
+    }
+
+    /// Calls `visit` with every Lox value the object holds
+    fn trace(&self, _visit: &mut dyn FnMut(&Value)) {}
+
+    /// Drops the Lox values the object holds, called when it is only kept alive by a cycle
+    fn clear(&self) {}
+
+    /// Number of bytes the object allocates itself, such as the buffer of a `Vec`. It counts
+    /// towards the memory limit and is measured again after every method call.
+    fn heap_size(&self) -> usize {
+        0
+    }
+}
+
+/// Host object stored in a [`Value`], cloning it creates another handle to the same object
+#[derive(Clone)]
+pub struct UserDataObject(pub(crate) Gc<dyn ErasedUserData>);
+
+impl UserDataObject {
+    pub fn new<T: UserData>(value: T) -> Self {
+        let boxed: Box<GcBox<dyn ErasedUserData>> = GcBox::new(value);
+        Self(Gc::from_box(boxed))
+    }
+
+    pub fn type_name(&self) -> &str {
+        self.0.type_name()
+    }
+
+    /// Returns the host value if it has the type `T`
+    pub fn downcast_ref<T: UserData>(&self) -> Option<&T> {
+        self.0.as_any().downcast_ref()
+    }
+
+    pub(crate) fn invoke(
+        &self,
+        name: &str,
+        ctx: &mut NativeContext,
+        args: &[Value],
+    ) -> Result<Value, VmError> {
+        self.0.invoke(name, ctx, args)
+    }
+}
+
+/// Object safe version of [`UserData`]
+pub(crate) trait ErasedUserData: fmt::Display {
+    fn type_name(&self) -> &str;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn equals(&self, other: &dyn ErasedUserData) -> bool;
+
+    fn invoke(&self, name: &str, ctx: &mut NativeContext, args: &[Value])
+        -> Result<Value, VmError>;
+
+    fn trace(&self, visit: &mut dyn FnMut(&Value));
+
+    fn clear(&self);
+
+    fn heap_size(&self) -> usize;
+}
+
+impl dyn ErasedUserData {
+    /// Size of the host value, including what it allocates itself
+    pub(crate) fn size(&self) -> usize {
+        mem::size_of_val(self) + self.heap_size()
+    }
+}
+
+impl<T: UserData> ErasedUserData for T {
+    fn type_name(&self) -> &str {
+        UserData::type_name(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn equals(&self, other: &dyn ErasedUserData) -> bool {
+        other
+            .as_any()
+            .downcast_ref::<T>()
+            .is_some_and(|other| UserData::equals(self, other))
+    }
+
+    fn invoke(
+        &self,
+        name: &str,
+        ctx: &mut NativeContext,
+        args: &[Value],
+    ) -> Result<Value, VmError> {
+        let Some(method) = T::METHODS.iter().find(|method| method.name == name) else {
+            return Err(VmError::new(
+                ErrorKind::UndefinedProperty,
+                format!("{} has no method '{name}'", self.type_name()),
+            ));
+        };
+
+        let arg_count = args.len() as u8;
+        if !method.arity.accepts(arg_count) {
+            return Err(VmError::new(
+                ErrorKind::Arity,
+                format!(
+                    "{}.{name} expected {} arguments, got {arg_count}",
+                    self.type_name(),
+                    method.arity
+                ),
+            ));
+        }
+
+        (method.function)(self, ctx, args)
+    }
+
+    fn trace(&self, visit: &mut dyn FnMut(&Value)) {
+        UserData::trace(self, visit);
+    }
+
+    fn clear(&self) {
+        UserData::clear(self);
+    }
+
+    fn heap_size(&self) -> usize {
+        UserData::heap_size(self)
+    }
+}
+
+impl PartialEq for UserDataObject {
+    fn eq(&self, other: &Self) -> bool {
+        Gc::ptr_eq(&self.0, &other.0) || self.0.equals(&*other.0)
+    }
+}
+
+impl fmt::Display for UserDataObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Debug for UserDataObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UserDataObject({})", self.type_name())
+    }
+}
@@ -1,11 +1,16 @@
 use std::{
+    cmp::Ordering,
     fmt::{self},
-    rc::Rc,
 };
 
-use crate::object::Object;
+use crate::{
+    bigint::{BigInt, MAX_SAFE_INTEGER},
+    gc::Gc,
+    object::Object,
+    userdata::{UserData, UserDataObject},
+};
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Number(f64),
     Boolean(bool),
@@ -13,6 +18,64 @@ pub enum Value {
     Nil,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+impl ArithmeticOp {
+    fn apply_f64(self, a: f64, b: f64) -> f64 {
+        match self {
+            ArithmeticOp::Add => a + b,
+            ArithmeticOp::Subtract => a - b,
+            ArithmeticOp::Multiply => a * b,
+            ArithmeticOp::Divide => a / b,
+            ArithmeticOp::Modulo => a % b,
+        }
+    }
+
+    fn apply_big(self, a: &BigInt, b: &BigInt) -> Value {
+        match self {
+            ArithmeticOp::Add => (a + b).into(),
+            ArithmeticOp::Subtract => (a - b).into(),
+            ArithmeticOp::Multiply => (a * b).into(),
+            // Division stays exact when there is no remainder and falls back to floats
+            // otherwise, the same way it behaves for small integers
+            ArithmeticOp::Divide => match a.div_rem(b) {
+                Some((quotient, remainder)) if remainder.is_zero() => quotient.into(),
+                _ => Value::Number(a.to_f64() / b.to_f64()),
+            },
+            ArithmeticOp::Modulo => match a.div_rem(b) {
+                Some((_, remainder)) => remainder.into(),
+                None => Value::Number(f64::NAN),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonOp {
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+impl ComparisonOp {
+    fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            ComparisonOp::Greater => ordering == Ordering::Greater,
+            ComparisonOp::GreaterEqual => ordering != Ordering::Less,
+            ComparisonOp::Less => ordering == Ordering::Less,
+            ComparisonOp::LessEqual => ordering != Ordering::Greater,
+        }
+    }
+}
+
 use Value::*;
 impl Value {
     pub fn as_bool(&self) -> Option<bool> {
@@ -43,6 +106,19 @@ impl Value {
         }
     }
 
+    /// Returns the exact integer value of an integral number or a big integer
+    pub fn as_integer(&self) -> Option<BigInt> {
+        match self {
+            Number(value) => BigInt::from_f64(*value),
+            Object(Object::BigInt(value)) => Some(BigInt::clone(value)),
+            _ => None,
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Number(_) | Object(Object::BigInt(_)))
+    }
+
     pub fn is_falsey(&self) -> bool {
         match self {
             Boolean(value) => !value,
@@ -59,9 +135,138 @@ impl Value {
         }
     }
 
-    pub fn new_string(value: impl Into<Rc<str>>) -> Self {
+    pub fn new_string(value: impl Into<Gc<str>>) -> Self {
         Self::Object(Object::String(value.into()))
     }
+
+    /// Wraps a host object so that it can be passed to scripts
+    pub fn new_userdata<T: UserData>(value: T) -> Self {
+        Self::Object(Object::UserData(UserDataObject::new(value)))
+    }
+
+    pub fn as_userdata<T: UserData>(&self) -> Option<&T> {
+        match self {
+            Object(Object::UserData(value)) => value.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Applies an arithmetic operator, returns `None` if either operand is not a number.
+    /// Integer results that can't be represented exactly by a float are promoted to big integers.
+    pub fn arithmetic(&self, op: ArithmeticOp, rhs: &Value) -> Option<Value> {
+        match (self, rhs) {
+            (Number(a), Number(b)) => {
+                let result = op.apply_f64(*a, *b);
+
+                let can_overflow = matches!(
+                    op,
+                    ArithmeticOp::Add | ArithmeticOp::Subtract | ArithmeticOp::Multiply
+                );
+                if can_overflow
+                    && result.abs() > MAX_SAFE_INTEGER
+                    && is_safe_integer(*a)
+                    && is_safe_integer(*b)
+                {
+                    let (a, b) = (BigInt::from_f64(*a)?, BigInt::from_f64(*b)?);
+                    Some(op.apply_big(&a, &b))
+                } else {
+                    Some(Number(result))
+                }
+            }
+            _ if self.is_numeric() && rhs.is_numeric() => {
+                match (self.as_integer(), rhs.as_integer()) {
+                    (Some(a), Some(b)) => Some(op.apply_big(&a, &b)),
+                    _ => Some(Number(op.apply_f64(self.to_f64()?, rhs.to_f64()?))),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Compares two numbers, returns `None` if either operand is not a number.
+    /// `>` and `<` are false for NaN, `<=` and `>=` are their negations and true for NaN.
+    pub fn compare(&self, op: ComparisonOp, rhs: &Value) -> Option<bool> {
+        if !self.is_numeric() || !rhs.is_numeric() {
+            return None;
+        }
+
+        match self.numeric_cmp(rhs) {
+            Some(ordering) => Some(op.matches(ordering)),
+            None => Some(matches!(
+                op,
+                ComparisonOp::GreaterEqual | ComparisonOp::LessEqual
+            )),
+        }
+    }
+
+    /// Equality for values held by a VM. All of their strings are interned, so strings are
+    /// compared by address only.
+    pub(crate) fn equals_interned(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Object(Object::String(a)), Object(Object::String(b))) => Gc::ptr_eq(a, b),
+            _ => self == other,
+        }
+    }
+
+    /// Negates a number or reverses a string, returns `None` for other values
+    pub fn negate(&self) -> Option<Value> {
+        match self {
+            Number(value) => Some(Number(value * -1.0)),
+            Object(Object::String(str)) => {
+                Some(Value::new_string(str.chars().rev().collect::<String>()))
+            }
+            Object(Object::BigInt(value)) => Some(Object(Object::BigInt(Gc::new(-&**value)))),
+            _ => None,
+        }
+    }
+
+    /// Compares two numeric values exactly, returns `None` for non-numbers and NaN
+    pub fn numeric_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Number(a), Number(b)) => a.partial_cmp(b),
+            (Object(Object::BigInt(a)), Number(b)) => (**a).partial_cmp(b),
+            (Number(a), Object(Object::BigInt(b))) => (**b).partial_cmp(a).map(Ordering::reverse),
+            (Object(Object::BigInt(a)), Object(Object::BigInt(b))) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        match self {
+            Number(value) => Some(*value),
+            Object(Object::BigInt(value)) => Some(value.to_f64()),
+            _ => None,
+        }
+    }
+}
+
+fn is_safe_integer(value: f64) -> bool {
+    value.fract() == 0.0 && value.abs() <= MAX_SAFE_INTEGER
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Number(a), Number(b)) => a == b,
+            (Boolean(a), Boolean(b)) => a == b,
+            (Object(Object::BigInt(a)), Number(b)) | (Number(b), Object(Object::BigInt(a))) => {
+                **a == *b
+            }
+            (Object(a), Object(b)) => a == b,
+            (Nil, Nil) => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<BigInt> for Value {
+    /// Big integers that fit into the exact float range are stored as regular numbers
+    fn from(value: BigInt) -> Self {
+        match value.to_safe_f64() {
+            Some(number) => Number(number),
+            None => Object(Object::BigInt(Gc::new(value))),
+        }
+    }
 }
 
 impl fmt::Display for Value {
@@ -81,3 +286,66 @@ impl_enum_conversions! {
     Number, f64,
     Object, Object,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ArithmeticOp, Value};
+    use crate::bigint::BigInt;
+
+    fn big(text: &str) -> Value {
+        BigInt::parse(text).unwrap().into()
+    }
+
+    #[test]
+    fn promotes_on_overflow() {
+        let max = Value::Number(9007199254740991.0);
+
+        let sum = max
+            .arithmetic(ArithmeticOp::Add, &Value::Number(2.0))
+            .unwrap();
+        assert_eq!(big("9007199254740993"), sum);
+        assert_eq!("9007199254740993", sum.to_string());
+
+        let product = max.arithmetic(ArithmeticOp::Multiply, &max).unwrap();
+        assert_eq!(big("81129638414606663681390495662081"), product);
+
+        let back = product.arithmetic(ArithmeticOp::Divide, &max).unwrap();
+        assert_eq!(Value::Number(9007199254740991.0), back);
+        assert!(matches!(back, Value::Number(_)));
+
+        let remainder = sum.arithmetic(ArithmeticOp::Modulo, &Value::Number(10.0));
+        assert_eq!(Some(Value::Number(3.0)), remainder);
+    }
+
+    #[test]
+    fn keeps_float_semantics() {
+        let fraction = Value::Number(0.5)
+            .arithmetic(ArithmeticOp::Multiply, &Value::Number(1e300))
+            .unwrap();
+        assert_eq!(Value::Number(5e299), fraction);
+
+        let inexact = big("9007199254740993")
+            .arithmetic(ArithmeticOp::Divide, &Value::Number(2.0))
+            .unwrap();
+        assert_eq!(Value::Number(4503599627370496.0), inexact);
+
+        let infinity = big("9007199254740993")
+            .arithmetic(ArithmeticOp::Divide, &Value::Number(0.0))
+            .unwrap();
+        assert_eq!(Value::Number(f64::INFINITY), infinity);
+
+        assert!(Value::Nil
+            .arithmetic(ArithmeticOp::Add, &Value::Number(1.0))
+            .is_none());
+    }
+
+    #[test]
+    fn compares_with_small_numbers() {
+        assert_eq!(big("1000000000000000000000"), Value::Number(1e21));
+        assert_ne!(big("1000000000000000000001"), Value::Number(1e21));
+        assert_eq!(
+            Some(std::cmp::Ordering::Greater),
+            big("9007199254740993").numeric_cmp(&Value::Number(9007199254740992.0))
+        );
+    }
+}
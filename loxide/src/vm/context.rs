@@ -0,0 +1,38 @@
+use super::{ErrorKind, Vm, VmError};
+use crate::{gc::Gc, value::Value};
+
+/// Access to the VM for native functions while they are running
+pub struct NativeContext<'vm> {
+    vm: &'vm mut Vm,
+}
+
+impl<'vm> NativeContext<'vm> {
+    pub(super) fn new(vm: &'vm mut Vm) -> Self {
+        Self { vm }
+    }
+
+    /// Creates a string, shared with the equal strings that the VM already holds
+    pub fn alloc_string(&mut self, value: impl AsRef<str> + Into<Gc<str>>) -> Value {
+        Value::new_string(self.vm.heap.intern(value))
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.vm.get_global(name)
+    }
+
+    /// Calls a Lox function or native, for natives that take callbacks.
+    /// Errors should usually be returned from the native as they are.
+    pub fn call_function(&mut self, callee: &Value, args: &[Value]) -> Result<Value, VmError> {
+        self.vm.call_function(callee, args)
+    }
+
+    /// Prints a line to the VM's output, the same way the `print` statement does
+    pub fn print(&mut self, value: &Value) -> Result<(), VmError> {
+        self.vm.print(value)
+    }
+
+    /// Creates a runtime error to return from the native function
+    pub fn error(&self, message: impl Into<String>) -> VmError {
+        VmError::new(ErrorKind::Native, message)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::diagnostic::render_snippet;
+use std::{error::Error, fmt, ops::Range, rc::Rc};
+
+/// Error that aborted the execution of a script
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// Active calls at the time of the error, innermost first
+    pub trace: Vec<TraceFrame>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An operand had a type that the operation doesn't support
+    Type,
+    UndefinedVariable,
+    /// A script compiled without a VM needs more global variables than the VM can address
+    TooManyGlobals,
+    /// A method was called that the object doesn't have
+    UndefinedProperty,
+    /// A function was called with the wrong number of arguments
+    Arity,
+    NotCallable,
+    StackOverflow,
+    /// The instruction budget set with [`Vm::set_fuel`](crate::Vm::set_fuel) was used up
+    OutOfFuel,
+    /// The deadline set with [`Vm::set_deadline`](crate::Vm::set_deadline) has passed
+    Timeout,
+    /// The memory limit in [`VmConfig`](crate::VmConfig) was reached
+    OutOfMemory,
+    /// The script was stopped with an [`InterruptHandle`](crate::InterruptHandle)
+    Interrupted,
+    /// Writing the output of the script failed
+    Io,
+    /// Reported by a native function
+    Native,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub function: Rc<str>,
+    pub line: u32,
+    pub column: u32,
+    /// Byte range in the source of the instruction that was executing
+    pub span: Range<usize>,
+}
+
+impl VmError {
+    /// Creates an error without a trace, which is filled in by the VM when a native function
+    /// returns it
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            trace: Vec::new(),
+        }
+    }
+
+    /// Renders the error with the source of the innermost frame, followed by the stack trace
+    pub fn render(&self, source: &str) -> String {
+        let mut output = format!("error: {}", self.message);
+
+        if let Some(frame) = self.trace.first() {
+            output.push('\n');
+            output.push_str(&render_snippet(
+                source,
+                frame.line,
+                frame.column,
+                &frame.span,
+            ));
+        }
+        for frame in &self.trace {
+            output.push_str(&format!("\n  {frame}"));
+        }
+
+        output
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.trace {
+            write!(f, "\n{frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[line {}, column {}] in {}",
+            self.line, self.column, self.function
+        )
+    }
+}
+
+impl Error for VmError {}
@@ -0,0 +1,55 @@
+use super::interner::Symbol;
+use crate::{gc::Gc, value::Value};
+use std::collections::HashMap;
+
+/// Global variables, stored in slots that the compiler resolves their names to.
+///
+/// A name gets a slot the first time compiled code mentions it, so a function can use a global
+/// that is only defined after the function is compiled, or in a later REPL line.
+#[derive(Default)]
+pub(super) struct Globals {
+    /// `None` for globals that have been mentioned but not defined yet
+    values: Vec<Option<Value>>,
+    names: Vec<Gc<str>>,
+    slots: HashMap<Symbol, usize>,
+}
+
+impl Globals {
+    /// Returns the slot of a global, adding an undefined one if it doesn't have one yet.
+    /// `name` must be interned.
+    pub fn slot(&mut self, name: Gc<str>) -> usize {
+        let next = self.values.len();
+        *self.slots.entry(Symbol(name.clone())).or_insert_with(|| {
+            self.values.push(None);
+            self.names.push(name);
+            next
+        })
+    }
+
+    /// Returns the slot of a global if it has one, `name` must be interned
+    pub fn find(&self, name: Gc<str>) -> Option<usize> {
+        self.slots.get(&Symbol(name)).copied()
+    }
+
+    pub fn name(&self, slot: usize) -> &Gc<str> {
+        &self.names[slot]
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&Value> {
+        self.values[slot].as_ref()
+    }
+
+    pub fn get_mut(&mut self, slot: usize) -> Option<&mut Value> {
+        self.values[slot].as_mut()
+    }
+
+    /// Defines a global or replaces its value if it's already defined
+    pub fn define(&mut self, slot: usize, value: Value) {
+        self.values[slot] = Some(value);
+    }
+
+    /// Values of the defined globals
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.values.iter().flatten()
+    }
+}
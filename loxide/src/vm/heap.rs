@@ -0,0 +1,377 @@
+use super::interner::Interner;
+use crate::{
+    bigint::BigInt,
+    gc::{Gc, GcBox, GcHeader},
+    object::{FunctionObject, NativeFunction, Object},
+    userdata::ErasedUserData,
+    value::Value,
+};
+use std::{iter, mem, ptr::NonNull};
+
+/// Bookkeeping stored in front of every object
+const GC_HEADER: usize = mem::size_of::<GcHeader>();
+/// The next collection happens once the heap has grown by this factor
+const GROWTH_FACTOR: usize = 2;
+
+/// Owner of the objects that scripts, the compiler and the host pass to the VM, with a mark and
+/// sweep collector that frees them.
+///
+/// Values refer to objects through [`Gc`] handles. Objects are adopted by the heap when they are
+/// tracked or first reached by a collection, and from then on only the heap frees them.
+/// Collections mark everything reachable from the roots, which are the value stack, the call
+/// frames, the globals and the objects of the functions being compiled, and sweep the rest.
+///
+/// The host also keeps handles, in its own variables and in host objects, that the VM can't see.
+/// Collections count the handles that the objects of the heap hold to each other, and an object
+/// with more handles than that is held from outside of the heap and treated as a root. Unreached
+/// host objects are cleared to break the cycles between them, and an unreached object is freed
+/// once no handle refers to it anymore, so handles never dangle.
+pub(super) struct Heap {
+    /// Id of the VM, recorded as the owner of its objects
+    id: u64,
+    objects: Vec<HeapObject>,
+    /// Total size of the tracked objects, including unreachable ones that haven't been swept yet
+    allocated: usize,
+    peak: usize,
+    next_gc: usize,
+    /// Objects allocated by the compiler, reachable only through the functions that are still
+    /// being compiled until compilation finishes
+    compiler_roots: Vec<Value>,
+    strings: Interner,
+}
+
+/// Object owned by the heap, referred to without a handle
+#[derive(Clone, Copy)]
+enum HeapObject {
+    String(NonNull<GcBox<str>>),
+    BigInt(NonNull<GcBox<BigInt>>),
+    Function(NonNull<GcBox<FunctionObject>>),
+    NativeFunction(NonNull<GcBox<NativeFunction>>),
+    UserData(NonNull<GcBox<dyn ErasedUserData>>),
+}
+
+// SAFETY for the methods of `HeapObject`: the heap only refers to objects that it owns and
+// hasn't freed yet, or that a value it is looking at holds a handle to
+impl HeapObject {
+    fn of(value: &Value) -> Option<Self> {
+        let Value::Object(object) = value else {
+            return None;
+        };
+        Some(match object {
+            Object::String(str) => HeapObject::String(Gc::as_ptr(str)),
+            Object::BigInt(value) => HeapObject::BigInt(Gc::as_ptr(value)),
+            Object::Function(function) => HeapObject::Function(Gc::as_ptr(function)),
+            Object::NativeFunction(native) => HeapObject::NativeFunction(Gc::as_ptr(native)),
+            Object::UserData(object) => HeapObject::UserData(Gc::as_ptr(&object.0)),
+        })
+    }
+
+    fn header(&self) -> &GcHeader {
+        unsafe {
+            match self {
+                HeapObject::String(ptr) => &ptr.as_ref().header,
+                HeapObject::BigInt(ptr) => &ptr.as_ref().header,
+                HeapObject::Function(ptr) => &ptr.as_ref().header,
+                HeapObject::NativeFunction(ptr) => &ptr.as_ref().header,
+                HeapObject::UserData(ptr) => &ptr.as_ref().header,
+            }
+        }
+    }
+
+    /// Calls `visit` with every value the object holds
+    fn trace(&self, visit: &mut dyn FnMut(&Value)) {
+        unsafe {
+            match self {
+                HeapObject::Function(ptr) => {
+                    ptr.as_ref().value.chunk.constants.iter().for_each(visit)
+                }
+                HeapObject::UserData(ptr) => ptr.as_ref().value.trace(visit),
+                _ => {}
+            }
+        }
+    }
+
+    fn clear(&self) {
+        if let HeapObject::UserData(ptr) = self {
+            unsafe { ptr.as_ref().value.clear() }
+        }
+    }
+
+    /// Number of bytes charged for the object. Functions aren't charged, they are only created
+    /// by the compiler and the host.
+    fn measure(&self) -> Option<usize> {
+        unsafe {
+            match self {
+                HeapObject::String(ptr) => Some(GC_HEADER + ptr.as_ref().value.len()),
+                HeapObject::BigInt(ptr) => {
+                    Some(GC_HEADER + mem::size_of::<BigInt>() + ptr.as_ref().value.heap_size())
+                }
+                HeapObject::UserData(ptr) => Some(GC_HEADER + ptr.as_ref().value.size()),
+                HeapObject::Function(_) | HeapObject::NativeFunction(_) => None,
+            }
+        }
+    }
+
+    /// # Safety
+    /// No handle may refer to the object anymore.
+    unsafe fn free(self) {
+        match self {
+            HeapObject::String(ptr) => Gc::free(ptr),
+            HeapObject::BigInt(ptr) => Gc::free(ptr),
+            HeapObject::Function(ptr) => Gc::free(ptr),
+            HeapObject::NativeFunction(ptr) => Gc::free(ptr),
+            HeapObject::UserData(ptr) => Gc::free(ptr),
+        }
+    }
+}
+
+impl Heap {
+    pub fn new(id: u64, gc_threshold: usize) -> Self {
+        Self {
+            id,
+            objects: Vec::new(),
+            allocated: 0,
+            peak: 0,
+            next_gc: gc_threshold,
+            compiler_roots: Vec::new(),
+            strings: Interner::default(),
+        }
+    }
+
+    pub fn allocated(&self) -> usize {
+        self.allocated
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.allocated > self.next_gc
+    }
+
+    /// Takes ownership of an object that isn't owned by a heap yet. Returns false if it is owned
+    /// by the heap of another VM, which keeps freeing it.
+    fn adopt(&mut self, object: HeapObject) -> bool {
+        let header = object.header();
+        if header.owner() == 0 {
+            header.set_owner(self.id);
+            self.objects.push(object);
+        }
+        header.owner() == self.id
+    }
+
+    /// Starts tracking an object, adopting it if necessary. Returns false if it doesn't count
+    /// towards the memory usage, is already tracked or belongs to another VM.
+    pub fn track(&mut self, value: &Value) -> bool {
+        let Some(object) = HeapObject::of(value) else {
+            return false;
+        };
+        if !self.adopt(object) {
+            return false;
+        }
+
+        let header = object.header();
+        match object.measure() {
+            Some(size) if header.size.get() == 0 => {
+                header.size.set(size);
+                self.allocated += size;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Measures a tracked object again after it may have changed its size, such as a host object
+    /// after one of its methods ran. Starts tracking it if it isn't tracked yet. Returns true if
+    /// the object grew.
+    pub fn update_size(&mut self, value: &Value) -> bool {
+        let Some(object) = HeapObject::of(value) else {
+            return false;
+        };
+        let header = object.header();
+        if header.owner() != self.id || header.size.get() == 0 {
+            return self.track(value);
+        }
+
+        let Some(size) = object.measure() else {
+            return false;
+        };
+        let old_size = header.size.replace(size);
+        self.allocated = self.allocated - old_size + size;
+        size > old_size
+    }
+
+    /// Records the current usage in the peak, called once the limits have been checked
+    pub fn update_peak(&mut self) {
+        self.peak = self.peak.max(self.allocated);
+    }
+
+    /// Returns the interned copy of a string, which still has to be tracked if it's new
+    pub fn intern<S: AsRef<str> + Into<Gc<str>>>(&mut self, str: S) -> Gc<str> {
+        if let Some(interned) = self.strings.get(str.as_ref()) {
+            return interned;
+        }
+
+        let mut str = str.into();
+        // Strings that already belong to a heap but aren't interned in this one are copied
+        if Gc::header(&str).owner() != 0 {
+            str = Gc::from(&*str);
+        }
+        self.adopt(HeapObject::String(Gc::as_ptr(&str)));
+        self.strings.insert(&str);
+        str
+    }
+
+    /// Returns the interned copy of a string if there is one
+    pub fn interned(&self, str: &str) -> Option<Gc<str>> {
+        self.strings.get(str)
+    }
+
+    pub fn root_compiled(&mut self, value: Value) {
+        self.compiler_roots.push(value);
+    }
+
+    /// Called when compilation finishes, from then on the objects are reachable through the
+    /// compiled function
+    pub fn release_compiler_roots(&mut self) {
+        self.compiler_roots.clear();
+    }
+
+    pub fn collect<'v>(
+        &mut self,
+        roots: impl IntoIterator<Item = &'v Value>,
+        functions: impl IntoIterator<Item = &'v Gc<FunctionObject>>,
+        gc_threshold: usize,
+    ) {
+        let id = self.id;
+        for object in &self.objects {
+            object.trace(&mut |child| {
+                if let Some(child) = HeapObject::of(child) {
+                    let header = child.header();
+                    if header.owner() == id {
+                        header.internal.set(header.internal.get() + 1);
+                    }
+                }
+            });
+        }
+
+        let mut gray = Vec::new();
+        for root in roots {
+            self.mark(root, &mut gray);
+        }
+        for function in functions {
+            self.mark_object(HeapObject::Function(Gc::as_ptr(function)), &mut gray);
+        }
+        let compiler_roots = mem::take(&mut self.compiler_roots);
+        for root in &compiler_roots {
+            self.mark(root, &mut gray);
+        }
+        self.compiler_roots = compiler_roots;
+
+        // Handles that don't come from other objects of the heap are held by the host
+        for index in 0..self.objects.len() {
+            let object = self.objects[index];
+            let header = object.header();
+            if header.handles() > header.internal.get() {
+                self.mark_object(object, &mut gray);
+            }
+        }
+        self.trace_gray(&mut gray);
+
+        self.sweep();
+        self.allocated = 0;
+        for object in &self.objects {
+            let header = object.header();
+            header.marked.set(false);
+            header.internal.set(0);
+
+            // Host objects can grow or shrink without the VM noticing
+            if let HeapObject::UserData(_) = object {
+                header.size.set(object.measure().unwrap_or_default());
+            }
+            self.allocated += header.size.get();
+        }
+        self.next_gc = (self.allocated * GROWTH_FACTOR).max(gc_threshold);
+    }
+
+    fn mark(&mut self, value: &Value, gray: &mut Vec<HeapObject>) {
+        if let Some(object) = HeapObject::of(value) {
+            self.mark_object(object, gray);
+        }
+    }
+
+    /// Marks an object, adopting it if this is the first time it's reached. Objects of other
+    /// VMs are left to their own heaps.
+    fn mark_object(&mut self, object: HeapObject, gray: &mut Vec<HeapObject>) {
+        if self.adopt(object) && !object.header().marked.replace(true) {
+            gray.push(object);
+        }
+    }
+
+    fn trace_gray(&mut self, gray: &mut Vec<HeapObject>) {
+        while let Some(object) = gray.pop() {
+            let mut children = Vec::new();
+            object.trace(&mut |child| self.mark(child, &mut children));
+            gray.append(&mut children);
+        }
+    }
+
+    /// Frees the unmarked objects. Unmarked host objects are cleared first, which drops the
+    /// handles of the cycles between them. Objects that still have handles are kept, they are
+    /// referenced by a host object that doesn't trace all of its values.
+    fn sweep(&mut self) {
+        for object in &self.objects {
+            if !object.header().marked.get() {
+                object.clear();
+            }
+        }
+
+        // Freeing an object drops its handles, which can leave more objects without any
+        let mut freed = true;
+        while freed {
+            freed = false;
+            let mut index = 0;
+            while index < self.objects.len() {
+                let object = self.objects[index];
+                let header = object.header();
+                if header.marked.get() || header.handles() > 0 {
+                    index += 1;
+                    continue;
+                }
+
+                self.objects.swap_remove(index);
+                if let HeapObject::String(ptr) = object {
+                    self.strings.remove(ptr);
+                }
+                // SAFETY: the heap owns the object and no handle refers to it
+                unsafe { object.free() };
+                freed = true;
+            }
+        }
+    }
+}
+
+impl Drop for Heap {
+    /// Frees the garbage and hands the objects that the host still holds over to their handles,
+    /// which free them once the last one is dropped
+    fn drop(&mut self) {
+        self.compiler_roots.clear();
+        self.collect(iter::empty(), iter::empty(), 0);
+
+        for object in &self.objects {
+            object.header().set_owner(0);
+        }
+        // Objects without handles can only be left by host objects that trace values they don't
+        // hold. Nothing refers to them, so freeing them doesn't free the other ones.
+        let unreferenced: Vec<_> = self
+            .objects
+            .drain(..)
+            .filter(|object| object.header().handles() == 0)
+            .collect();
+        for object in unreferenced {
+            // SAFETY: no handle refers to the object, and no heap owns it anymore
+            unsafe { object.free() };
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::gc::{Gc, GcBox};
+use std::{
+    borrow::Borrow,
+    collections::HashSet,
+    hash::{Hash, Hasher},
+    ptr::{self, NonNull},
+};
+
+/// Strings shared by the whole VM, so that equal strings are stored only once.
+///
+/// The interner doesn't keep its strings alive: they are owned by the heap, which removes them
+/// from the interner when it frees them.
+#[derive(Default)]
+pub(super) struct Interner {
+    strings: HashSet<Interned>,
+}
+
+impl Interner {
+    /// Returns the interned copy of `str` if there is one
+    pub fn get(&self, str: &str) -> Option<Gc<str>> {
+        self.strings
+            .get(str)
+            // SAFETY: interned strings are alive until they're removed
+            .map(|interned| unsafe { Gc::from_ptr(interned.0) })
+    }
+
+    /// Adds a string owned by the heap, which must not be interned yet
+    pub fn insert(&mut self, str: &Gc<str>) {
+        self.strings.insert(Interned(Gc::as_ptr(str)));
+    }
+
+    /// Removes a string that is about to be freed if it is the interned copy
+    pub fn remove(&mut self, ptr: NonNull<GcBox<str>>) {
+        // SAFETY: the string hasn't been freed yet
+        let str = unsafe { &ptr.as_ref().value };
+        if self
+            .strings
+            .get(str)
+            .is_some_and(|interned| ptr::addr_eq(interned.0.as_ptr(), ptr.as_ptr()))
+        {
+            self.strings.remove(str);
+        }
+    }
+}
+
+/// Interned string, hashed and compared by its contents
+struct Interned(NonNull<GcBox<str>>);
+
+impl Borrow<str> for Interned {
+    fn borrow(&self) -> &str {
+        // SAFETY: interned strings are alive until they're removed
+        unsafe { &self.0.as_ref().value }
+    }
+}
+
+impl PartialEq for Interned {
+    fn eq(&self, other: &Self) -> bool {
+        <Self as Borrow<str>>::borrow(self) == <Self as Borrow<str>>::borrow(other)
+    }
+}
+
+impl Eq for Interned {}
+
+impl Hash for Interned {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        <Self as Borrow<str>>::borrow(self).hash(state);
+    }
+}
+
+/// Interned string used as a map key, hashed and compared by address
+#[derive(Debug, Clone)]
+pub(super) struct Symbol(pub Gc<str>);
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Gc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Gc::as_ptr(&self.0).as_ptr() as *const u8 as usize).hash(state);
+    }
+}
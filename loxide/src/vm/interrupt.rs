@@ -0,0 +1,26 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Stops the script running on a [`Vm`](super::Vm) from another thread or a signal handler.
+/// Created with [`Vm::interrupt_handle`](super::Vm::interrupt_handle).
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Makes the running script stop with [`ErrorKind::Interrupted`](super::ErrorKind)
+    /// at its next loop iteration or call.
+    /// Only sets a flag, so it's safe to call from a signal handler.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub(super) fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
@@ -1,66 +1,291 @@
+mod context;
+mod error;
+mod globals;
+mod heap;
+mod interner;
+mod interrupt;
 mod native;
+mod output;
 
+pub use context::NativeContext;
+pub use error::{ErrorKind, TraceFrame, VmError};
+pub use interrupt::InterruptHandle;
+pub use output::CaptureBuffer;
+
+use self::{globals::Globals, heap::Heap};
 use crate::{
-    object::{FunctionObject, Object},
+    compiler,
+    convert::TypedNative,
+    diagnostic::Diagnostic,
+    gc::Gc,
+    object::{Arity, FunctionObject, NativeFunction, Object},
     op_code::OpCode,
-    value::Value,
+    value::{ArithmeticOp, ComparisonOp, Value},
 };
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    io::{self, Write},
     rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
 };
 
 const INITIAL_STACK_SIZE: usize = 256;
-const FRAMES_MAX: usize = 64;
+const INITIAL_FRAMES: usize = 64;
+/// Number of budget checks between reads of the clock when a deadline is set
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+/// Id of the next VM, 0 is left for functions that don't belong to one
+static NEXT_VM_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Function implemented by the host, receives the arguments of the call.
+/// Returned errors abort the script like any other runtime error.
+pub type NativeFn = dyn Fn(&mut NativeContext, &[Value]) -> Result<Value, VmError>;
 
 pub struct Vm {
-    frames: [CallFrame; FRAMES_MAX],
-    frame_count: usize,
+    /// Identifies the functions compiled for this VM, which refer to its global slots
+    id: u64,
+    config: VmConfig,
+    frames: Vec<CallFrame>,
     stack: Vec<Value>,
-    globals: HashMap<Rc<str>, Value>,
+    globals: Globals,
+    heap: Heap,
+    /// Receives the output of `print`
+    output: Box<dyn Write>,
+    /// Receives errors and warnings reported by the host through the VM
+    diagnostics: Box<dyn Write>,
+    /// Remaining loop iterations and calls, unlimited if `None`
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    budget_checks: u32,
+    interrupt: InterruptHandle,
+    /// Number of `run` loops active on the Rust stack, more than one means a native called back
+    /// into the VM
+    nesting: usize,
+    /// Set when the last error left the VM in a state that can be resumed
+    resumable: bool,
+    /// Base frame depth and stack length of the run that was stopped by the budget
+    suspended: Option<(usize, usize)>,
+}
+
+/// Limits that are fixed for the lifetime of a [`Vm`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmConfig {
+    /// Maximum depth of nested calls
+    pub max_frames: usize,
+    /// Maximum number of values on the stack, checked when entering a function
+    pub max_stack: usize,
+    /// Maximum number of nested calls from natives back into the VM. Each of them runs on the
+    /// Rust stack, so the limit has to fit into the stack of the host's thread.
+    pub max_nesting: usize,
+    /// Maximum number of bytes used by strings and other objects created by scripts,
+    /// unlimited if `None`
+    pub max_memory: Option<usize>,
+    /// Number of allocated bytes that triggers the first garbage collection, later ones
+    /// happen when the heap has doubled since the last one
+    pub gc_threshold: usize,
+    /// Collects garbage on every allocation, to find objects that aren't rooted correctly
+    pub gc_stress: bool,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            max_frames: 1 << 14,
+            max_stack: 1 << 20,
+            max_nesting: 100,
+            max_memory: None,
+            gc_threshold: 1 << 20,
+            gc_stress: false,
+        }
+    }
 }
 
-#[derive(Debug, Default)]
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
 struct CallFrame {
-    function: FunctionObject,
+    function: Gc<FunctionObject>,
+    /// Code of `function`, cached to save two indirections on every instruction
+    code: *const [u8],
     ip: usize,
     stack_offset: usize,
 }
 
 impl CallFrame {
-    fn new(function: FunctionObject, stack_offset: usize) -> Self {
+    fn new(function: Gc<FunctionObject>, stack_offset: usize) -> Self {
+        // Copies the callee into the frame like calls did before functions were shared, so that
+        // benches/fibonacci.rs can measure the difference
+        #[cfg(feature = "clone_calls")]
+        let function = Gc::new(FunctionObject::clone(&function));
+
         Self {
+            code: function.chunk.code.as_slice(),
             function,
             ip: 0,
             stack_offset,
         }
     }
+
+    #[inline(always)]
+    fn code(&self) -> &[u8] {
+        // SAFETY: the frame keeps `function` alive, and functions are never modified once
+        // they are shared
+        unsafe { &*self.code }
+    }
 }
 
 impl Vm {
     pub fn new() -> Self {
+        Self::with_config(VmConfig::default())
+    }
+
+    pub fn with_config(config: VmConfig) -> Self {
+        let id = NEXT_VM_ID.fetch_add(1, Ordering::Relaxed);
         let mut vm = Self {
+            id,
+            config,
             stack: Vec::with_capacity(INITIAL_STACK_SIZE),
-            globals: HashMap::new(),
-            frames: std::array::from_fn(|_| CallFrame::default()),
-            frame_count: 0,
+            globals: Globals::default(),
+            heap: Heap::new(id, config.gc_threshold),
+            frames: Vec::with_capacity(INITIAL_FRAMES),
+            output: Box::new(io::stdout()),
+            diagnostics: Box::new(io::stderr()),
+            fuel: None,
+            deadline: None,
+            budget_checks: 0,
+            interrupt: InterruptHandle::default(),
+            nesting: 0,
+            resumable: false,
+            suspended: None,
         };
-        vm.define_native_fn("clock".into(), native::clock);
+        vm.define_native("clock", 0, native::clock);
+        vm.define_native("parse_int", 1, native::parse_int);
+        vm.define_native("to_decimal", 1, native::to_decimal);
+        vm.define_native("to_hex", 1, native::to_hex);
         vm
     }
 
-    pub fn interpret(&mut self, function: FunctionObject) -> InterpretResult {
+    /// Compiles a script with the objects it needs allocated on this VM's heap, see
+    /// [`compile`](crate::compile)
+    pub fn compile(&mut self, source: &str) -> Result<FunctionObject, Vec<Diagnostic>> {
+        self.compile_with_warnings(source)
+            .map(|(function, _)| function)
+    }
+
+    /// Compiles a script with the objects it needs allocated on this VM's heap, see
+    /// [`compile_with_warnings`](crate::compile_with_warnings)
+    pub fn compile_with_warnings(
+        &mut self,
+        source: &str,
+    ) -> Result<(FunctionObject, Vec<Diagnostic>), Vec<Diagnostic>> {
+        compiler::compile_in(source, Some(self))
+    }
+
+    pub fn interpret(&mut self, mut function: FunctionObject) -> InterpretResult {
+        if function.unlinked_globals.is_some() {
+            self.link(&mut function)?;
+        }
+
         self.stack.clear();
         self.stack.shrink_to(INITIAL_STACK_SIZE);
+        self.frames.clear();
+        self.frames.shrink_to(INITIAL_FRAMES);
+        self.suspended = None;
+        // Interrupts are meant for the script that was running when they were requested
+        self.interrupt.reset();
+
+        self.call_function(&Value::Object(Object::from(function)), &[])
+            .map(Some)
+    }
+
+    /// Calls a Lox function or native with the given arguments and runs it until it returns.
+    /// Can be used both by the host and by natives that take callbacks. If the call fails, the
+    /// stack and call frames are restored to the state before the call, unless it ran out of fuel
+    /// or time and can be continued with [`Vm::resume`].
+    pub fn call_function(&mut self, callee: &Value, args: &[Value]) -> Result<Value, VmError> {
+        if self.nesting == self.config.max_nesting {
+            return Err(VmError {
+                kind: ErrorKind::StackOverflow,
+                message: format!("Stack overflow at native call depth {}", self.nesting),
+                trace: self.stack_trace(),
+            });
+        }
+
+        let base_depth = self.frames.len();
+        let base_stack = self.stack.len();
 
-        self.stack
-            .push(Value::Object(Object::Function(function.clone())));
-        self.call(function, 0)?;
+        self.nesting += 1;
+        let result = self.run_call(callee, args, base_depth);
+        self.nesting -= 1;
+        self.finish_run(result, base_depth, base_stack)
+    }
+
+    /// Continues a script that was stopped with [`ErrorKind::OutOfFuel`] or
+    /// [`ErrorKind::Timeout`], after adding fuel or extending the deadline.
+    /// Returns `Ok(None)` if there is nothing to resume.
+    pub fn resume(&mut self) -> InterpretResult {
+        let Some((base_depth, base_stack)) = self.suspended.take() else {
+            return Ok(None);
+        };
 
-        self.run()
+        self.nesting += 1;
+        let result = self.run(base_depth);
+        self.nesting -= 1;
+        self.finish_run(result, base_depth, base_stack).map(Some)
+    }
+
+    fn finish_run(
+        &mut self,
+        result: Result<Value, VmError>,
+        base_depth: usize,
+        base_stack: usize,
+    ) -> Result<Value, VmError> {
+        if result.is_err() {
+            if self.resumable {
+                self.resumable = false;
+                self.suspended = Some((base_depth, base_stack));
+            } else {
+                self.frames.truncate(base_depth);
+                self.stack.truncate(base_stack);
+            }
+        }
+        result
     }
 
-    fn run(&mut self) -> InterpretResult {
+    fn run_call(
+        &mut self,
+        callee: &Value,
+        args: &[Value],
+        base_depth: usize,
+    ) -> Result<Value, VmError> {
+        let Ok(arg_count) = u8::try_from(args.len()) else {
+            return Err(VmError::new(
+                ErrorKind::Arity,
+                format!("Can't call a function with {} arguments", args.len()),
+            ));
+        };
+
+        let callee = self.intern_value(callee.clone());
+        self.stack.push(callee.clone());
+        for arg in args {
+            let arg = self.intern_value(arg.clone());
+            self.stack.push(arg);
+        }
+        self.call_value(callee, arg_count)?;
+
+        if self.frames.len() > base_depth {
+            self.run(base_depth)
+        } else {
+            // Natives don't push a frame and have already left their result on the stack
+            Ok(self.stack.pop().unwrap())
+        }
+    }
+
+    /// Executes instructions until the frame at `base_depth` returns
+    fn run(&mut self, base_depth: usize) -> Result<Value, VmError> {
         loop {
             #[cfg(feature = "trace")]
             {
@@ -85,51 +310,62 @@ impl Vm {
                 Return => {
                     let result = self.stack.pop().unwrap();
 
-                    let old_stack_offset = self.current_frame().stack_offset;
-
-                    self.frame_count -= 1;
+                    let old_stack_offset = self.frames.pop().unwrap().stack_offset;
+                    self.stack.drain(old_stack_offset..);
 
-                    if self.frame_count == 0 {
-                        break InterpretResult::Ok(Some(result));
+                    if self.frames.len() == base_depth {
+                        break Ok(result);
                     }
-
-                    self.stack.drain(old_stack_offset..);
                     self.stack.push(result);
                 }
-                Constant => {
-                    let value = self.read_constant();
-                    self.stack.push(value);
-                }
-                LongConstant => {
-                    let value = self.read_long_constant();
+                Constant | LongConstant => {
+                    let value = self.read_constant(op_code);
                     self.stack.push(value);
                 }
                 Negate => match self.peek_mut(0) {
                     Value::Number(value) => *value *= -1.0,
-                    Value::Object(Object::String(str)) => {
-                        let reversed: String = str.chars().rev().collect();
-                        *str = reversed.into();
-                    }
-                    _ => self.runtime_error("Operand must be a number or a string")?,
+                    value => match value.negate() {
+                        Some(result) => {
+                            *value = result;
+                            self.track_allocation()?;
+                        }
+                        None => self.runtime_error(
+                            ErrorKind::Type,
+                            "Operand must be a number or a string",
+                        )?,
+                    },
                 },
                 Add => match (self.peek(0), self.peek(1)) {
                     (Value::Object(Object::String(_)), Value::Object(Object::String(_))) => {
                         let b = self.stack.pop().unwrap();
                         let a = self.stack.pop().unwrap();
-                        let new_value = format!("{}{}", a.as_str().unwrap(), b.as_str().unwrap());
-                        self.stack.push(Value::new_string(new_value));
+                        let (a, b) = (a.as_str().unwrap(), b.as_str().unwrap());
+
+                        // Checked up front so that a script can't build a string much larger
+                        // than the limit
+                        self.reserve_memory(a.len() + b.len())?;
+                        self.stack.push(Value::new_string(format!("{a}{b}")));
+                        self.track_allocation()?;
                     }
-                    _ => self.binary_op(|a, b| a + b)?,
+                    _ => self.arithmetic(ArithmeticOp::Add)?,
                 },
-                Subtract => self.binary_op(|a, b| a - b)?,
-                Multiply => self.binary_op(|a, b| a * b)?,
-                Divide => self.binary_op(|a, b| a / b)?,
-                Greater => self.binary_op(|a, b| a > b)?,
-                Less => self.binary_op(|a, b| a < b)?,
+                Subtract => self.arithmetic(ArithmeticOp::Subtract)?,
+                Multiply => self.arithmetic(ArithmeticOp::Multiply)?,
+                Divide => self.arithmetic(ArithmeticOp::Divide)?,
+                Modulo => self.arithmetic(ArithmeticOp::Modulo)?,
+                Greater => self.comparison(ComparisonOp::Greater)?,
+                GreaterEqual => self.comparison(ComparisonOp::GreaterEqual)?,
+                Less => self.comparison(ComparisonOp::Less)?,
+                LessEqual => self.comparison(ComparisonOp::LessEqual)?,
                 Equal => {
                     let b = self.stack.pop().unwrap();
                     let a = self.stack.pop().unwrap();
-                    self.stack.push((a == b).into());
+                    self.stack.push(a.equals_interned(&b).into());
+                }
+                NotEqual => {
+                    let b = self.stack.pop().unwrap();
+                    let a = self.stack.pop().unwrap();
+                    self.stack.push((!a.equals_interned(&b)).into());
                 }
                 Nil => self.stack.push(Value::Nil),
                 True => self.stack.push(true.into()),
@@ -140,147 +376,483 @@ impl Vm {
                 }
                 Print => {
                     let value = self.stack.pop().unwrap();
-                    println!("{value}");
+                    self.print(&value)?;
                 }
                 Pop => {
                     self.stack.pop();
                 }
-                DefineGlobal => {
-                    let name = self.read_string();
-                    self.globals.insert(name, self.peek(0).clone());
-                    self.stack.pop();
+                DefineGlobal | LongDefineGlobal => {
+                    let slot = self.read_index(op_code);
+                    let value = self.stack.pop().unwrap();
+                    self.globals.define(slot, value);
                 }
-                GetGlobal => {
-                    let name = self.read_string();
-                    match self.globals.get(&name) {
+                GetGlobal | LongGetGlobal => {
+                    let slot = self.read_index(op_code);
+                    match self.globals.get(slot) {
                         Some(value) => self.stack.push(value.clone()),
-                        None => self.runtime_error(&format!("Undefined variable {name}"))?,
+                        None => self.undefined_global(slot)?,
                     }
                 }
-                SetGlobal => {
-                    let name = self.read_string();
-
-                    match self.globals.entry(name) {
-                        Entry::Occupied(mut o) => {
-                            let value = self.stack.last().unwrap().clone();
-                            o.insert(value);
-                        }
-                        Entry::Vacant(v) => {
-                            let name = v.into_key();
-                            self.runtime_error(&format!("Undefined variable '{name}'"))?;
-                        }
+                SetGlobal | LongSetGlobal => {
+                    let slot = self.read_index(op_code);
+                    let value = self.stack.last().unwrap().clone();
+                    match self.globals.get_mut(slot) {
+                        Some(global) => *global = value,
+                        None => self.undefined_global(slot)?,
                     }
                 }
-                GetLocal => {
-                    let slot = self.read_byte() as usize + self.current_frame().stack_offset;
+                GetLocal | LongGetLocal => {
+                    let slot = self.read_index(op_code) + self.current_frame().stack_offset;
                     let value = self.stack[slot].clone();
                     self.stack.push(value);
                 }
-                SetLocal => {
-                    let slot = self.read_byte() as usize + self.current_frame().stack_offset;
+                SetLocal | LongSetLocal => {
+                    let slot = self.read_index(op_code) + self.current_frame().stack_offset;
                     self.stack[slot] = self.peek(0).clone();
                 }
-                JumpIfFalse => {
-                    let offset = self.read_u16();
+                JumpIfFalse | LongJumpIfFalse => {
+                    let offset = self.read_jump(op_code);
                     if self.peek(0).is_falsey() {
-                        self.current_frame().ip += offset as usize;
+                        self.current_frame().ip += offset;
                     }
                 }
-                Jump => {
-                    let offset = self.read_u16();
-                    self.current_frame().ip += offset as usize;
+                Jump | LongJump => {
+                    let offset = self.read_jump(op_code);
+                    self.current_frame().ip += offset;
                 }
-                Loop => {
-                    let offset = self.read_u16();
-                    self.current_frame().ip -= offset as usize;
+                Loop | LongLoop => {
+                    self.check_budget()?;
+                    let offset = self.read_jump(op_code);
+                    self.current_frame().ip -= offset;
                 }
                 Call => {
+                    self.check_budget()?;
                     let arg_count = self.read_byte();
                     self.call_value(self.peek(arg_count as usize).clone(), arg_count)?;
                 }
+                Invoke | LongInvoke => {
+                    self.check_budget()?;
+                    let name = self.read_string(op_code);
+                    let arg_count = self.read_byte();
+                    self.invoke(&name, arg_count)?;
+                }
+                TailCall => {
+                    self.check_budget()?;
+                    let arg_count = self.read_byte();
+                    match self.peek(arg_count as usize).clone() {
+                        Value::Object(Object::Function(funct)) => {
+                            self.tail_call(funct, arg_count)?
+                        }
+                        // Natives don't use a frame, the following `Return` passes on their result
+                        callee => self.call_value(callee, arg_count)?,
+                    }
+                }
             }
         }
     }
 
-    fn runtime_error(&self, message: &str) -> Result<(), VmError> {
-        for i in (0..self.frame_count).rev() {
-            let frame = &self.frames[i];
-            let funct = &frame.function;
-            eprintln!(
-                "[line {}] in {}: {message}",
-                frame.function.chunk.line_at(frame.ip),
-                funct.name,
-            );
+    fn runtime_error(&self, kind: ErrorKind, message: &str) -> Result<(), VmError> {
+        Err(VmError {
+            kind,
+            message: message.to_owned(),
+            trace: self.stack_trace(),
+        })
+    }
+
+    /// Checks for interrupts and charges one unit of fuel, called right after reading a backward jump or a call so that
+    /// straight-line code runs without checks.
+    /// A stopped script continues by executing the same instruction again.
+    #[inline]
+    fn check_budget(&mut self) -> Result<(), VmError> {
+        if self.interrupt.is_interrupted() {
+            self.interrupt.reset();
+            return self.runtime_error(ErrorKind::Interrupted, "Interrupted");
+        }
+
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return self.suspend(ErrorKind::OutOfFuel, "Ran out of fuel");
+            }
+            *fuel -= 1;
+        }
+
+        if let Some(deadline) = self.deadline {
+            self.budget_checks = self.budget_checks.wrapping_add(1);
+            if self.budget_checks.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                && Instant::now() >= deadline
+            {
+                return self.suspend(ErrorKind::Timeout, "Ran out of time");
+            }
+        }
+        Ok(())
+    }
+
+    fn suspend(&mut self, kind: ErrorKind, message: &str) -> Result<(), VmError> {
+        let error = self.runtime_error(kind, message);
+
+        // Natives that called back into the VM can't be suspended, so the script can only
+        // be resumed if the budget ran out in the outermost run
+        if self.nesting == 1 {
+            self.resumable = true;
+            self.current_frame().ip -= 1;
+        }
+        error
+    }
+
+    /// Registers the value on top of the stack with the heap if it's a new object, strings are
+    /// replaced by their interned copy
+    fn track_allocation(&mut self) -> Result<(), VmError> {
+        let value = self.stack.pop().unwrap();
+        let value = self.intern_value(value);
+        self.stack.push(value);
+        if self.heap.track(self.stack.last().unwrap()) {
+            self.reserve_memory(0)?;
+            if self.config.gc_stress || self.heap.should_collect() {
+                self.collect_garbage();
+            }
+            self.heap.update_peak();
+        }
+        Ok(())
+    }
+
+    /// Registers an object created by the compiler, which is rooted until compilation ends
+    pub(crate) fn track_compiled(&mut self, value: &Value) {
+        if self.heap.track(value) {
+            self.heap.root_compiled(value.clone());
+            if self.config.gc_stress || self.heap.should_collect() {
+                self.collect_garbage();
+            }
+            self.heap.update_peak();
+        }
+    }
+
+    /// Replaces a string with its interned copy. Every string that scripts can reach is
+    /// interned, so that the VM can compare strings by address.
+    fn intern_value(&mut self, value: Value) -> Value {
+        match value {
+            Value::Object(Object::String(str)) => Value::new_string(self.heap.intern(str)),
+            value => value,
+        }
+    }
+
+    /// Returns the interned copy of a string for the compiler, which still has to track it
+    pub(crate) fn intern(&mut self, str: &str) -> Gc<str> {
+        self.heap.intern(str)
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the slot of a global for the compiler, see [`Globals::slot`]
+    pub(crate) fn global_slot(&mut self, name: &str) -> usize {
+        let name = self.heap.intern(name);
+        self.globals.slot(name)
+    }
+
+    fn undefined_global(&self, slot: usize) -> Result<(), VmError> {
+        let name = self.globals.name(slot);
+        self.runtime_error(
+            ErrorKind::UndefinedVariable,
+            &format!("Undefined variable '{name}'"),
+        )
+    }
+
+    /// Points the global instructions of a script compiled without a VM to this VM's slots, and
+    /// moves the objects it allocated onto this VM's heap
+    fn link(&mut self, function: &mut FunctionObject) -> Result<(), VmError> {
+        let names = function.unlinked_globals.take().unwrap_or_default();
+        let mut slots = Vec::with_capacity(names.len());
+        for name in names {
+            let slot = self.global_slot(&name);
+            if slot > OpCode::MAX_LONG_INDEX {
+                return Err(VmError::new(
+                    ErrorKind::TooManyGlobals,
+                    format!("Too many global variables to link '{name}'"),
+                ));
+            }
+            slots.push(slot);
+        }
+        self.link_function(function, &slots);
+        self.heap.update_peak();
+        Ok(())
+    }
+
+    /// Replaces the slots in the global instructions of `function` and the functions declared in
+    /// it, and interns and tracks their constants. Scripts compiled without a VM only use the
+    /// long forms, so that any slot fits.
+    fn link_function(&mut self, function: &mut FunctionObject, slots: &[usize]) {
+        function.vm_id = self.id;
+        let chunk = &mut function.chunk;
+
+        let mut offset = 0;
+        while offset < chunk.code.len() {
+            let op_code = OpCode::from_byte(chunk.code[offset]).expect("Read invalid opcode");
+            if let OpCode::LongDefineGlobal | OpCode::LongGetGlobal | OpCode::LongSetGlobal =
+                op_code
+            {
+                let operand = &mut chunk.code[offset + 1..offset + 4];
+                let slot = u32::from_le_bytes([operand[0], operand[1], operand[2], 0]) as usize;
+                operand.copy_from_slice(&(slots[slot] as u32).to_le_bytes()[0..3]);
+            }
+            offset += 1 + op_code.operand_len();
+        }
+
+        for constant in &mut chunk.constants {
+            match constant {
+                Value::Object(Object::Function(nested)) => {
+                    self.link_function(Gc::make_mut(nested), slots)
+                }
+                Value::Object(Object::String(str)) => *str = self.heap.intern(str.clone()),
+                _ => {}
+            }
+            // Only tracked, the collection waits until the function is rooted by its frame
+            self.heap.track(constant);
+        }
+    }
+
+    pub(crate) fn finish_compile(&mut self) {
+        self.heap.release_compiler_roots();
+    }
+
+    /// Frees the objects that are neither reachable from scripts nor held by the host, and
+    /// updates the memory usage. Runs automatically as objects are allocated.
+    pub fn collect_garbage(&mut self) {
+        let roots = self.stack.iter().chain(self.globals.values());
+        let functions = self.frames.iter().map(|frame| &frame.function);
+        self.heap
+            .collect(roots, functions, self.config.gc_threshold);
+    }
+
+    /// Fails if allocating `size` more bytes would exceed the memory limit
+    fn reserve_memory(&mut self, size: usize) -> Result<(), VmError> {
+        let Some(limit) = self.config.max_memory else {
+            return Ok(());
+        };
+
+        if self.heap.allocated() + size > limit {
+            self.collect_garbage();
+            let allocated = self.heap.allocated();
+            if allocated + size > limit {
+                return self.runtime_error(
+                    ErrorKind::OutOfMemory,
+                    &format!(
+                        "Out of memory (allocating {size} bytes with {allocated} of {limit} in use)"
+                    ),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of bytes used by objects that scripts created and that are still alive
+    pub fn memory_usage(&mut self) -> usize {
+        self.collect_garbage();
+        self.heap.allocated()
+    }
+
+    /// Highest memory usage so far. Objects are only freed by garbage collections, so this can
+    /// include some objects that were no longer in use.
+    pub fn peak_memory_usage(&self) -> usize {
+        self.heap.peak()
+    }
+
+    /// Returns a handle that stops the running script from another thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Limits the number of loop iterations and calls the VM executes, `None` removes the
+    /// limit. Running out stops the script with [`ErrorKind::OutOfFuel`].
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Remaining fuel, `None` if there is no limit
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Stops scripts that are still running at `deadline` with [`ErrorKind::Timeout`],
+    /// `None` removes the limit. The clock is only checked periodically, so scripts can run
+    /// slightly past the deadline.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+        self.budget_checks = 0;
+    }
+
+    fn stack_trace(&self) -> Vec<TraceFrame> {
+        self.frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                // The instruction pointer has already moved past the failed instruction
+                let location = frame.function.chunk.location_at(frame.ip.saturating_sub(1));
+                TraceFrame {
+                    function: frame.function.name.clone(),
+                    line: location.line,
+                    column: location.column,
+                    span: location.span(),
+                }
+            })
+            .collect()
+    }
+
+    /// Fills in the trace of an error returned by a native
+    fn with_trace(&self, mut error: VmError) -> VmError {
+        if error.trace.is_empty() {
+            error.trace = self.stack_trace();
         }
+        error
+    }
+
+    fn print(&mut self, value: &Value) -> Result<(), VmError> {
+        if let Err(err) = writeln!(self.output, "{value}") {
+            self.runtime_error(ErrorKind::Io, &format!("Failed to write output: {err}"))?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the writer that receives the output of `print`, stdout by default
+    pub fn set_output(&mut self, writer: impl Write + 'static) {
+        self.output = Box::new(writer);
+    }
+
+    /// Replaces the writer used by [`Vm::report_error`] and [`Vm::report_diagnostic`],
+    /// stderr by default
+    pub fn set_diagnostics(&mut self, writer: impl Write + 'static) {
+        self.diagnostics = Box::new(writer);
+    }
+
+    /// Writes a rendered runtime error to the diagnostics writer
+    pub fn report_error(&mut self, error: &VmError, source: &str) -> io::Result<()> {
+        writeln!(self.diagnostics, "{}", error.render(source))
+    }
+
+    /// Writes a rendered compiler diagnostic to the diagnostics writer
+    pub fn report_diagnostic(&mut self, diagnostic: &Diagnostic, source: &str) -> io::Result<()> {
+        writeln!(self.diagnostics, "{}", diagnostic.render(source))
+    }
+
+    /// Makes a host function available to scripts as a global variable. The function can be a
+    /// closure that captures host state.
+    /// Calls with an argument count outside of `arity` fail before reaching the function.
+    pub fn define_native<F>(&mut self, name: &str, arity: impl Into<Arity>, function: F)
+    where
+        F: Fn(&mut NativeContext, &[Value]) -> Result<Value, VmError> + 'static,
+    {
+        let native = NativeFunction {
+            name: name.into(),
+            arity: arity.into(),
+            function: Box::new(function),
+        };
+        self.set_global(name, Value::Object(Object::NativeFunction(Gc::new(native))));
+    }
 
-        Err(VmError::RuntimeError)
+    /// Makes a Rust function with typed parameters available to scripts, see [`TypedNative`].
+    /// Arguments are converted automatically and calls with mismatched types fail before
+    /// reaching the function.
+    pub fn define_typed_native<Args, F: TypedNative<Args>>(&mut self, name: &str, function: F) {
+        let native_name: Rc<str> = name.into();
+        self.define_native(name, F::arity(), move |_, args| {
+            function.call(&native_name, args)
+        });
     }
 
-    fn define_native_fn(&mut self, name: Rc<str>, funct: fn(&[Value]) -> Value) {
-        self.globals
-            .insert(name, Value::Object(Object::NativeFunction(funct)));
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let slot = self.globals.find(self.heap.interned(name)?)?;
+        self.globals.get(slot).cloned()
+    }
+
+    /// Defines a global variable or replaces its value if it already exists
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        let slot = self.global_slot(name);
+        let value = self.intern_value(value);
+        if self.heap.track(&value) {
+            self.heap.update_peak();
+        }
+        self.globals.define(slot, value);
     }
 
     #[inline(always)]
     fn current_frame(&mut self) -> &mut CallFrame {
-        &mut self.frames[self.frame_count - 1]
+        self.frames.last_mut().unwrap()
     }
 
     fn read_byte(&mut self) -> u8 {
         let frame = self.current_frame();
-        let byte = frame.function.chunk.code[frame.ip];
+        let byte = frame.code()[frame.ip];
         frame.ip += 1;
         byte
     }
 
-    fn read_multi<const LEN: usize>(&mut self) -> &[u8] {
+    fn read_multi<const LEN: usize>(&mut self) -> [u8; LEN] {
         let frame = self.current_frame();
-        let data = &frame.function.chunk.code[frame.ip..frame.ip + LEN];
+        let data = frame.code()[frame.ip..frame.ip + LEN].try_into().unwrap();
         frame.ip += LEN;
         data
     }
 
-    fn read_constant(&mut self) -> Value {
-        let index = self.read_byte();
-        self.current_frame().function.chunk.constants[index as usize].clone()
+    /// Reads the index operand of an instruction, which is 24 bits long in the long forms
+    fn read_index(&mut self, op_code: OpCode) -> usize {
+        if op_code.is_long() {
+            let data = self.read_multi::<3>();
+            u32::from_le_bytes([data[0], data[1], data[2], 0]) as usize
+        } else {
+            self.read_byte() as usize
+        }
     }
 
-    fn read_long_constant(&mut self) -> Value {
-        let data = self.read_multi::<3>();
-        let mut index_data = [0; 4];
-        index_data[0..3].copy_from_slice(data);
-
-        let index = u32::from_le_bytes(index_data);
-        self.current_frame().function.chunk.constants[index as usize].clone()
+    fn read_constant(&mut self, op_code: OpCode) -> Value {
+        let index = self.read_index(op_code);
+        self.current_frame().function.chunk.constants[index].clone()
     }
 
-    fn read_string(&mut self) -> Rc<str> {
-        match self.read_constant() {
+    fn read_string(&mut self, op_code: OpCode) -> Gc<str> {
+        match self.read_constant(op_code) {
             Value::Object(Object::String(name)) => name,
-            _ => panic!("Global name should be a string"),
+            _ => panic!("Method name should be a string"),
         }
     }
 
-    fn read_u16(&mut self) -> u16 {
-        let data = self.read_multi::<2>();
-        u16::from_ne_bytes(data.try_into().unwrap())
+    /// Reads the distance of a jump, which is 32 bits long in the long forms
+    fn read_jump(&mut self, op_code: OpCode) -> usize {
+        if op_code.is_long() {
+            u32::from_ne_bytes(self.read_multi()) as usize
+        } else {
+            u16::from_ne_bytes(self.read_multi()) as usize
+        }
     }
 
-    fn binary_op<V, Op>(&mut self, op: Op) -> Result<(), VmError>
-    where
-        V: Into<Value>,
-        Op: FnOnce(f64, f64) -> V,
-    {
+    fn arithmetic(&mut self, op: ArithmeticOp) -> Result<(), VmError> {
         let b = self.stack.pop().unwrap();
         let a = self.stack.pop().unwrap();
 
-        match (&a, &b) {
-            (Value::Number(lhs), Value::Number(rhs)) => {
-                let result = op(*lhs, *rhs);
+        match a.arithmetic(op, &b) {
+            Some(result) => {
+                self.stack.push(result);
+                self.track_allocation()
+            }
+            None => self.runtime_error(
+                ErrorKind::Type,
+                &format!("Operands have invalid types (got {a} and {b})"),
+            ),
+        }
+    }
+
+    fn comparison(&mut self, op: ComparisonOp) -> Result<(), VmError> {
+        let b = self.stack.pop().unwrap();
+        let a = self.stack.pop().unwrap();
+
+        match a.compare(op, &b) {
+            Some(result) => {
                 self.stack.push(result.into());
                 Ok(())
             }
-            _ => self.runtime_error(&format!("Operands have invalid types (got {a} and {b})")),
+            None => self.runtime_error(
+                ErrorKind::Type,
+                &format!("Operands have invalid types (got {a} and {b})"),
+            ),
         }
     }
 
@@ -296,82 +868,199 @@ impl Vm {
     fn call_value(&mut self, callee: Value, arg_count: u8) -> Result<(), VmError> {
         match callee {
             Value::Object(Object::Function(funct)) => self.call(funct, arg_count),
-            Value::Object(Object::NativeFunction(f)) => {
+            Value::Object(Object::NativeFunction(native)) => {
+                if !native.arity.accepts(arg_count) {
+                    self.runtime_error(
+                        ErrorKind::Arity,
+                        &format!(
+                            "{} expected {} arguments, got {}",
+                            native.name, native.arity, arg_count
+                        ),
+                    )?;
+                }
+
                 let args: Vec<Value> = self
                     .stack
-                    .drain(self.stack.len() - 1 - arg_count as usize..)
+                    .drain(self.stack.len() - arg_count as usize..)
                     .collect();
-                let result = f(&args);
-                self.stack.push(result);
-                Ok(())
+                self.stack.pop();
+                let result = (native.function)(&mut NativeContext::new(self), &args);
+
+                match result {
+                    Ok(value) => {
+                        self.stack.push(value);
+                        self.track_allocation()
+                    }
+                    Err(error) => Err(self.with_trace(error)),
+                }
             }
             _ => {
-                self.runtime_error("Only functions and classes are callable")?;
+                self.runtime_error(
+                    ErrorKind::NotCallable,
+                    "Only functions and classes are callable",
+                )?;
                 Ok(())
             }
         }
     }
 
-    fn call(&mut self, funct: FunctionObject, arg_count: u8) -> Result<(), VmError> {
+    fn invoke(&mut self, name: &str, arg_count: u8) -> Result<(), VmError> {
+        let Value::Object(Object::UserData(receiver)) = self.peek(arg_count as usize).clone()
+        else {
+            let receiver = self.peek(arg_count as usize).clone();
+            return self.runtime_error(
+                ErrorKind::Type,
+                &format!("Only host objects have methods (got {receiver})"),
+            );
+        };
+
+        let args: Vec<Value> = self
+            .stack
+            .drain(self.stack.len() - arg_count as usize..)
+            .collect();
+        self.stack.pop();
+
+        match receiver.invoke(name, &mut NativeContext::new(self), &args) {
+            Ok(value) => {
+                self.stack.push(value);
+                self.track_allocation()?;
+
+                // Methods can grow their receiver, for example by pushing to a list
+                if self
+                    .heap
+                    .update_size(&Value::Object(Object::UserData(receiver)))
+                {
+                    self.reserve_memory(0)?;
+                    self.heap.update_peak();
+                }
+                Ok(())
+            }
+            Err(error) => Err(self.with_trace(error)),
+        }
+    }
+
+    fn check_arity(&self, funct: &FunctionObject, arg_count: u8) -> Result<(), VmError> {
         if arg_count != funct.arity {
-            self.runtime_error(&format!(
-                "Expected {} arugments, got {}",
-                funct.arity, arg_count
-            ))?;
+            self.runtime_error(
+                ErrorKind::Arity,
+                &format!("Expected {} arguments, got {}", funct.arity, arg_count),
+            )?;
         }
+        Ok(())
+    }
 
-        if self.frame_count == FRAMES_MAX {
-            self.runtime_error("Stack overflow")?;
+    /// Links a function compiled without a VM that is called without going through
+    /// [`Vm::interpret`], for example by the host with [`Vm::call_function`]. The caller's copy
+    /// stays unlinked, so it is linked again on every call.
+    ///
+    /// Fails for functions of another VM, their global instructions refer to that VM's slots.
+    fn linked(&mut self, mut funct: Gc<FunctionObject>) -> Result<Gc<FunctionObject>, VmError> {
+        if funct.vm_id != self.id {
+            if funct.unlinked_globals.is_some() {
+                self.link(Gc::make_mut(&mut funct))
+                    .map_err(|error| self.with_trace(error))?;
+            } else if funct.vm_id != 0 {
+                self.runtime_error(
+                    ErrorKind::NotCallable,
+                    &format!(
+                        "Can't call '{}', it was compiled for another VM",
+                        funct.name
+                    ),
+                )?;
+            }
+        }
+        Ok(funct)
+    }
+
+    fn call(&mut self, funct: Gc<FunctionObject>, arg_count: u8) -> Result<(), VmError> {
+        self.check_arity(&funct, arg_count)?;
+        let funct = self.linked(funct)?;
+
+        let depth = self.frames.len();
+        if depth == self.config.max_frames {
+            self.runtime_error(
+                ErrorKind::StackOverflow,
+                &format!("Stack overflow at call depth {depth}"),
+            )?;
+        }
+        if self.stack.len() > self.config.max_stack {
+            self.runtime_error(
+                ErrorKind::StackOverflow,
+                &format!(
+                    "Stack overflow at call depth {depth} ({} values on the stack)",
+                    self.stack.len()
+                ),
+            )?;
         }
 
         let frame = CallFrame::new(funct, self.stack.len() - arg_count as usize - 1);
-        self.frames[self.frame_count] = frame;
-        self.frame_count += 1;
+        self.frames.push(frame);
         Ok(())
     }
-}
 
-pub type InterpretResult = Result<Option<Value>, VmError>;
+    /// Replaces the current frame with a call to `funct`, moving the callee and its arguments
+    /// into the current stack window
+    fn tail_call(&mut self, funct: Gc<FunctionObject>, arg_count: u8) -> Result<(), VmError> {
+        self.check_arity(&funct, arg_count)?;
+        let funct = self.linked(funct)?;
 
-#[derive(Debug, PartialEq)]
-pub enum VmError {
-    RuntimeError,
+        let stack_offset = self.current_frame().stack_offset;
+        let callee_slot = self.stack.len() - arg_count as usize - 1;
+        self.stack.drain(stack_offset..callee_slot);
+
+        *self.current_frame() = CallFrame::new(funct, stack_offset);
+        Ok(())
+    }
 }
 
+pub type InterpretResult = Result<Option<Value>, VmError>;
+
 #[cfg(test)]
 mod tests {
-    use super::Vm;
+    use super::{ErrorKind, TraceFrame, Vm, VmConfig};
     use crate::{
-        chunk::Chunk, object::FunctionObject, op_code::OpCode, value::Value, vm::InterpretResult,
+        chunk::{Chunk, SourceLocation},
+        compiler::compile,
+        gc::Gc,
+        object::{FunctionObject, Object},
+        op_code::OpCode,
+        value::Value,
+        vm::InterpretResult,
     };
 
     #[test]
     fn basic_math() {
         let mut chunk = Chunk::default();
+        let location = SourceLocation {
+            line: 123,
+            column: 1,
+            ..Default::default()
+        };
 
         let constant = chunk.add_constant(1.2);
-        chunk.write(OpCode::Constant, 123);
-        chunk.write(constant as u8, 123);
+        chunk.write(OpCode::Constant, location);
+        chunk.write(constant as u8, location);
 
         let constant = chunk.add_constant(3.4);
-        chunk.write(OpCode::Constant, 123);
-        chunk.write(constant as u8, 123);
+        chunk.write(OpCode::Constant, location);
+        chunk.write(constant as u8, location);
 
-        chunk.write(OpCode::Add, 123);
+        chunk.write(OpCode::Add, location);
 
         let constant = chunk.add_constant(5.6);
-        chunk.write(OpCode::Constant, 123);
-        chunk.write(constant as u8, 123);
+        chunk.write(OpCode::Constant, location);
+        chunk.write(constant as u8, location);
 
-        chunk.write(OpCode::Divide, 123);
+        chunk.write(OpCode::Divide, location);
 
-        chunk.write(OpCode::Negate, 123);
-        chunk.write(OpCode::Return, 123);
+        chunk.write(OpCode::Negate, location);
+        chunk.write(OpCode::Return, location);
 
         let function = FunctionObject {
             arity: 0,
             chunk,
             name: "<main>".into(),
+            ..Default::default()
         };
 
         let result = Vm::new().interpret(function);
@@ -384,25 +1073,349 @@ mod tests {
     #[test]
     fn add_long_constants() {
         let mut chunk = Chunk::default();
+        let location = SourceLocation {
+            line: 123,
+            column: 1,
+            ..Default::default()
+        };
 
         let constant_long = chunk.add_constant(42.0);
-        chunk.write(OpCode::LongConstant, 123);
-        chunk.write_slice(&constant_long.to_le_bytes()[0..3], 123);
+        chunk.write(OpCode::LongConstant, location);
+        chunk.write_slice(&constant_long.to_le_bytes()[0..3], location);
 
         let constant_long = chunk.add_constant(3.0);
-        chunk.write(OpCode::LongConstant, 123);
-        chunk.write_slice(&constant_long.to_le_bytes()[0..3], 123);
+        chunk.write(OpCode::LongConstant, location);
+        chunk.write_slice(&constant_long.to_le_bytes()[0..3], location);
 
-        chunk.write(OpCode::Add, 123);
-        chunk.write(OpCode::Return, 123);
+        chunk.write(OpCode::Add, location);
+        chunk.write(OpCode::Return, location);
 
         let function = FunctionObject {
             arity: 0,
             chunk,
             name: "<main>".into(),
+            ..Default::default()
         };
 
         let result = Vm::new().interpret(function);
         assert_eq!(InterpretResult::Ok(Some(Value::Number(45.0))), result);
     }
+
+    #[test]
+    fn tail_calls_reuse_frames() {
+        let source = r#"
+fun count(n, total) {
+    if (n == 0) return total;
+    return count(n - 1, total + n);
+}
+var result = count(10000, 0);
+"#;
+
+        let mut vm = Vm::new();
+        vm.interpret(compile(source).unwrap()).unwrap();
+        assert_eq!(Some(Value::Number(50005000.0)), vm.get_global("result"));
+
+        let config = VmConfig {
+            max_frames: 64,
+            ..Default::default()
+        };
+        let mut vm = Vm::with_config(config);
+        vm.interpret(compile(source).unwrap()).unwrap();
+        assert_eq!(Some(Value::Number(50005000.0)), vm.get_global("result"));
+    }
+
+    #[test]
+    fn code_pointers_survive_frame_changes() {
+        let source = r#"
+fun count(n) {
+    if (n == 0) return 0;
+    return 1 + count(n - 1);
+}
+fun ping(n) {
+    if (n == 0) return "ping";
+    return pong(n - 1);
+}
+fun pong(n) {
+    if (n == 0) return "pong";
+    return ping(n - 1);
+}
+fun run() {
+    // From here on this function is only kept alive by its frame
+    run = nil;
+    var total = count(200);
+    total = total + count(100);
+    return ping(11) + " " + to_decimal(total);
+}
+var result = run();
+"#;
+
+        // The frames are reallocated when the recursion outgrows their initial capacity, and
+        // tail calls replace the function of a frame
+        let mut vm = Vm::new();
+        vm.interpret(compile(source).unwrap()).unwrap();
+        assert_eq!(Some(Value::new_string("pong 300")), vm.get_global("result"));
+        assert_eq!(Some(Value::Nil), vm.get_global("run"));
+        assert!(vm.frames.capacity() > super::INITIAL_FRAMES);
+
+        // Suspended frames keep their functions while the host drops its references
+        let mut vm = Vm::new();
+        vm.set_fuel(Some(50));
+        let error = vm.interpret(compile(source).unwrap()).unwrap_err();
+        assert_eq!(ErrorKind::OutOfFuel, error.kind);
+        assert_eq!(Some(Value::Nil), vm.get_global("run"));
+        vm.set_global("count", Value::Nil);
+        vm.set_fuel(None);
+        let error = vm.resume().unwrap_err();
+        assert_eq!(ErrorKind::NotCallable, error.kind);
+
+        let mut vm = Vm::new();
+        vm.set_fuel(Some(150));
+        vm.interpret(compile(source).unwrap()).unwrap_err();
+        vm.set_fuel(None);
+        vm.resume().unwrap();
+        assert_eq!(Some(Value::new_string("pong 300")), vm.get_global("result"));
+    }
+
+    #[test]
+    fn stack_limits() {
+        let source = r#"
+fun count(n) {
+    if (n == 0) return 0;
+    return 1 + count(n - 1);
+}
+var result = count(DEPTH);
+"#;
+
+        let config = VmConfig {
+            max_frames: 100,
+            ..Default::default()
+        };
+        let mut vm = Vm::with_config(config);
+        let function = compile(&source.replace("DEPTH", "98")).unwrap();
+        vm.interpret(function).unwrap();
+        assert_eq!(Some(Value::Number(98.0)), vm.get_global("result"));
+
+        let function = compile(&source.replace("DEPTH", "99")).unwrap();
+        let error = vm.interpret(function).unwrap_err();
+        assert_eq!(ErrorKind::StackOverflow, error.kind);
+        assert_eq!("Stack overflow at call depth 100", error.message);
+        assert_eq!(100, error.trace.len());
+
+        // The stack grows by two values per call: the callee and its argument
+        let config = VmConfig {
+            max_stack: 100,
+            ..Default::default()
+        };
+        let function = compile(&source.replace("DEPTH", "60")).unwrap();
+        let error = Vm::with_config(config).interpret(function).unwrap_err();
+        assert_eq!(ErrorKind::StackOverflow, error.kind);
+
+        let function = compile(&source.replace("DEPTH", "5000")).unwrap();
+        assert!(Vm::new().interpret(function).is_ok());
+    }
+
+    #[test]
+    fn nesting_limits() {
+        let source = r#"
+fun count(n) {
+    if (n == 0) return 0;
+    return call(count, n - 1) + 1;
+}
+var result = count(DEPTH);
+"#;
+        let limited_vm = || {
+            let mut vm = Vm::with_config(VmConfig {
+                max_nesting: 50,
+                ..Default::default()
+            });
+            vm.define_native("call", 2, |ctx, args| {
+                ctx.call_function(&args[0], &args[1..])
+            });
+            vm
+        };
+
+        // The script itself is the first nested run
+        let mut vm = limited_vm();
+        vm.interpret(compile(&source.replace("DEPTH", "49")).unwrap())
+            .unwrap();
+        assert_eq!(Some(Value::Number(49.0)), vm.get_global("result"));
+
+        let error = vm
+            .interpret(compile(&source.replace("DEPTH", "50")).unwrap())
+            .unwrap_err();
+        assert_eq!(ErrorKind::StackOverflow, error.kind);
+        assert_eq!("Stack overflow at native call depth 50", error.message);
+        assert_eq!(51, error.trace.len());
+
+        // The default limit stops deep callbacks before they overflow the 2MiB stack of a test thread
+        let mut vm = Vm::new();
+        vm.define_native("call", 2, |ctx, args| {
+            ctx.call_function(&args[0], &args[1..])
+        });
+        let function = compile(&source.replace("DEPTH", "100000")).unwrap();
+        let error = vm.interpret(function).unwrap_err();
+        assert_eq!(ErrorKind::StackOverflow, error.kind);
+    }
+
+    #[test]
+    fn runtime_error_trace() {
+        let source = r#"
+fun inner(value) {
+    return value + nil;
+}
+fun outer() {
+    var result = inner(1);
+    return result;
+}
+outer();
+"#;
+
+        let error = Vm::new().interpret(compile(source).unwrap()).unwrap_err();
+        assert_eq!(ErrorKind::Type, error.kind);
+        assert_eq!("Operands have invalid types (got 1 and nil)", error.message);
+
+        let frame = |function: &str, line, column, span| TraceFrame {
+            function: function.into(),
+            line,
+            column,
+            span,
+        };
+        assert_eq!(
+            vec![
+                frame("inner", 3, 18, 37..38),
+                frame("outer", 6, 25, 84..85),
+                frame("<main>", 9, 7, 114..115)
+            ],
+            error.trace
+        );
+        assert_eq!("+", &source[error.trace[0].span.clone()]);
+        assert_eq!(
+            "error: Operands have invalid types (got 1 and nil)
+ --> line 3, column 18
+  |
+3 |     return value + nil;
+  |                  ^
+  [line 3, column 18] in inner
+  [line 6, column 25] in outer
+  [line 9, column 7] in <main>",
+            error.render(source)
+        );
+        assert_eq!(
+            "Operands have invalid types (got 1 and nil)
+[line 3, column 18] in inner
+[line 6, column 25] in outer
+[line 9, column 7] in <main>",
+            error.to_string()
+        );
+
+        let error = Vm::new()
+            .interpret(compile("print missing;").unwrap())
+            .unwrap_err();
+        assert_eq!(ErrorKind::UndefinedVariable, error.kind);
+        assert_eq!("Undefined variable 'missing'", error.message);
+    }
+
+    #[test]
+    fn non_ascii_columns() {
+        let source = "print \"ééé\" + nil;";
+        let error = Vm::new().interpret(compile(source).unwrap()).unwrap_err();
+        assert_eq!(13, error.trace[0].column);
+        assert_eq!(
+            "error: Operands have invalid types (got ééé and nil)
+ --> line 1, column 13
+  |
+1 | print \"ééé\" + nil;
+  |             ^
+  [line 1, column 13] in <main>",
+            error.render(source)
+        );
+    }
+
+    #[test]
+    fn strings_are_interned() {
+        let mut vm = Vm::new();
+        let function = vm
+            .compile(r#"var a = "ab"; var b = "a" + "b"; var c = to_decimal(12);"#)
+            .unwrap();
+        vm.interpret(function).unwrap();
+
+        let string = |vm: &Vm, name| match vm.get_global(name) {
+            Some(Value::Object(Object::String(str))) => str,
+            value => panic!("{name} is {value:?}"),
+        };
+        assert!(Gc::ptr_eq(&string(&vm, "a"), &string(&vm, "b")));
+        assert!(Gc::ptr_eq(
+            &vm.heap.interned("12").unwrap(),
+            &string(&vm, "c")
+        ));
+
+        // Functions compiled without a VM can still use globals defined by it, and their
+        // strings are interned when they are linked
+        let function = compile(r#"var d = a + c; var e = "ab";"#).unwrap();
+        vm.interpret(function).unwrap();
+        assert_eq!(Some(Value::new_string("ab12")), vm.get_global("d"));
+        assert!(Gc::ptr_eq(&string(&vm, "a"), &string(&vm, "e")));
+
+        // Strings created by the host are interned when they're passed to the VM, so the VM
+        // can compare them by address
+        vm.set_global("host", Value::new_string("ab"));
+        vm.define_native("make", 0, |_, _| Ok(Value::new_string("ab")));
+        vm.define_native(
+            "alloc",
+            0,
+            |ctx, _| Ok(ctx.alloc_string(String::from("ab"))),
+        );
+        let function = vm
+            .compile(r#"fun is_ab(text) { return text == a and text != "b"; }"#)
+            .unwrap();
+        vm.interpret(function).unwrap();
+        let function = vm
+            .compile("var same = host == a and make() == a and alloc() == a;")
+            .unwrap();
+        vm.interpret(function).unwrap();
+        assert_eq!(Some(Value::Boolean(true)), vm.get_global("same"));
+        assert!(Gc::ptr_eq(&string(&vm, "a"), &string(&vm, "host")));
+
+        let is_ab = vm.get_global("is_ab").unwrap();
+        let result = vm.call_function(&is_ab, &[Value::new_string("ab")]);
+        assert_eq!(Ok(Value::Boolean(true)), result);
+    }
+
+    #[test]
+    fn global_slots() {
+        let mut vm = Vm::new();
+        let function = vm
+            .compile("fun get() { return later; } var a = 1;")
+            .unwrap();
+        vm.interpret(function).unwrap();
+
+        let function = vm.compile("get();").unwrap();
+        let error = vm.interpret(function).unwrap_err();
+        assert_eq!(ErrorKind::UndefinedVariable, error.kind);
+        assert_eq!("Undefined variable 'later'", error.message);
+
+        // Later scripts and scripts compiled without the VM share the slots
+        let function = vm.compile("var later = a + 1; var a = 10;").unwrap();
+        vm.interpret(function).unwrap();
+        let function = compile("var result = get() + a;").unwrap();
+        vm.interpret(function).unwrap();
+        assert_eq!(Some(Value::Number(12.0)), vm.get_global("result"));
+
+        vm.set_global("later", Value::Number(5.0));
+        let function = vm.compile("result = get();").unwrap();
+        vm.interpret(function).unwrap();
+        assert_eq!(Some(Value::Number(5.0)), vm.get_global("result"));
+        // Scripts compiled without a VM are also linked when the host calls them directly
+        let script = Value::Object(Object::from(
+            compile("var c = clock; var d = 1; var e = 2; var f = 3; var g = later + d;").unwrap(),
+        ));
+        assert_eq!(Ok(Value::Nil), vm.call_function(&script, &[]));
+        assert_eq!(Ok(Value::Nil), vm.call_function(&script, &[]));
+        assert_eq!(Some(Value::Number(6.0)), vm.get_global("g"));
+        assert_eq!(vm.get_global("clock"), vm.get_global("c"));
+        assert!(matches!(
+            vm.get_global("clock"),
+            Some(Value::Object(Object::NativeFunction(_)))
+        ));
+    }
 }
@@ -1,11 +1,49 @@
 use std::time::SystemTime;
 
-use crate::value::Value;
+use super::{ErrorKind, NativeContext, VmError};
+use crate::{
+    bigint::{BigInt, Radix},
+    value::Value,
+};
 
-pub fn clock(_args: &[Value]) -> Value {
+pub fn clock(ctx: &mut NativeContext, _args: &[Value]) -> Result<Value, VmError> {
     let timestamp = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
+        .map_err(|_| ctx.error("System clock is set before the UNIX epoch"))?
         .as_millis();
-    Value::Number(timestamp as f64)
+    Ok(Value::Number(timestamp as f64))
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal string into an exact integer,
+/// returns nil if the string is not a valid integer
+pub fn parse_int(_ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+    let text = args[0].as_str().ok_or_else(|| {
+        VmError::new(
+            ErrorKind::Type,
+            format!("parse_int expected a string, got {}", args[0]),
+        )
+    })?;
+    Ok(BigInt::parse(text.trim()).map_or(Value::Nil, Value::from))
+}
+
+pub fn to_decimal(ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+    integer_to_string(ctx, &args[0], Radix::Decimal)
+}
+
+pub fn to_hex(ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+    integer_to_string(ctx, &args[0], Radix::Hexadecimal)
+}
+
+fn integer_to_string(
+    ctx: &mut NativeContext,
+    value: &Value,
+    radix: Radix,
+) -> Result<Value, VmError> {
+    match value.as_integer() {
+        Some(value) => Ok(ctx.alloc_string(value.to_str_radix(radix))),
+        None => Err(VmError::new(
+            ErrorKind::Type,
+            format!("Expected an integer, got {value}"),
+        )),
+    }
 }
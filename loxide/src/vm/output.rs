@@ -0,0 +1,32 @@
+use std::{cell::RefCell, io, rc::Rc};
+
+/// In-memory writer that can be shared with a [`Vm`](super::Vm) to capture its output.
+/// Clones write to the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl CaptureBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns everything written so far, invalid UTF-8 is replaced
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl io::Write for CaptureBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,474 @@
+use loxide::{
+    compile, compile_with_warnings, ErrorKind, FromLox, IntoLox, List, NativeContext, Severity,
+    Value, Variadic, Vm, VmConfig, VmError,
+};
+use std::{cell::Cell, rc::Rc};
+
+fn run(vm: &mut Vm, source: &str) {
+    let function = compile(source).unwrap();
+    vm.interpret(function).unwrap();
+}
+
+#[test]
+fn read_globals() {
+    let mut vm = Vm::new();
+    run(
+        &mut vm,
+        r#"
+fun greet(name) {
+    return "Hello, " + name;
+}
+var greeting = greet("host");
+var big = 9007199254740991 * 10;
+"#,
+    );
+
+    assert_eq!(
+        Some(Value::new_string("Hello, host")),
+        vm.get_global("greeting")
+    );
+    assert_eq!(
+        "90071992547409910",
+        vm.get_global("big").unwrap().to_string()
+    );
+    assert_eq!(None, vm.get_global("missing"));
+}
+
+#[test]
+fn write_globals() {
+    let mut vm = Vm::new();
+    vm.set_global("limit", Value::Number(10.0));
+
+    run(
+        &mut vm,
+        r#"
+var total = 0;
+for (var i = 1; i <= limit; i = i + 1) {
+    total = total + i;
+}
+"#,
+    );
+    assert_eq!(Some(Value::Number(55.0)), vm.get_global("total"));
+
+    vm.set_global("total", Value::Number(1.0));
+    run(&mut vm, "total = total * 2;");
+    assert_eq!(Some(Value::Number(2.0)), vm.get_global("total"));
+}
+
+fn sum(ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+    let mut total = 0.0;
+    for arg in args {
+        total += arg
+            .as_number()
+            .ok_or_else(|| ctx.error(format!("Can't add {arg} to a sum")))?;
+    }
+    Ok(Value::Number(total))
+}
+
+fn describe(ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+    let prefix = ctx.get_global("prefix").unwrap_or(Value::Nil);
+    Ok(ctx.alloc_string(format!("{prefix}: {}", args[0])))
+}
+
+#[test]
+fn register_natives() {
+    let mut vm = Vm::new();
+    vm.define_native("sum", 1.., sum);
+    vm.define_native("describe", 1, describe);
+
+    run(
+        &mut vm,
+        r#"
+var result = sum(1, 2, 3);
+var prefix = "value";
+var description = describe(result);
+"#,
+    );
+    assert_eq!(Some(Value::Number(6.0)), vm.get_global("result"));
+    assert_eq!(
+        Some(Value::new_string("value: 6")),
+        vm.get_global("description")
+    );
+    assert_eq!(
+        "<native fun sum>",
+        vm.get_global("sum").unwrap().to_string()
+    );
+}
+
+#[test]
+fn native_errors() {
+    let mut vm = Vm::new();
+    vm.define_native("sum", 1..=3, sum);
+
+    let function = compile("fun total() {\n    return sum(1, \"two\");\n}\ntotal();").unwrap();
+    let error = vm.interpret(function).unwrap_err();
+    assert_eq!(ErrorKind::Native, error.kind);
+    assert_eq!("Can't add two to a sum", error.message);
+    let lines: Vec<u32> = error.trace.iter().map(|frame| frame.line).collect();
+    assert_eq!(vec![2, 4], lines);
+
+    let function = compile("sum();").unwrap();
+    let error = vm.interpret(function).unwrap_err();
+    assert_eq!(ErrorKind::Arity, error.kind);
+    assert_eq!("sum expected 1 to 3 arguments, got 0", error.message);
+
+    let function = compile("sum(1, 2, 3, 4);").unwrap();
+    assert_eq!(ErrorKind::Arity, vm.interpret(function).unwrap_err().kind);
+
+    let function = compile("to_hex(\"ff\");").unwrap();
+    assert_eq!(ErrorKind::Type, vm.interpret(function).unwrap_err().kind);
+}
+
+#[test]
+fn compile_errors() {
+    let diagnostics = compile("var = 1;").unwrap_err();
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(Severity::Error, diagnostics[0].severity);
+    assert_eq!("Expected a variable name", diagnostics[0].message);
+
+    let (_, warnings) = compile_with_warnings("{ var unused; }").unwrap();
+    assert_eq!(1, warnings.len());
+    assert_eq!(Severity::Warning, warnings[0].severity);
+}
+
+#[test]
+fn runtime_errors() {
+    let mut vm = Vm::with_config(VmConfig {
+        max_frames: 8,
+        ..Default::default()
+    });
+
+    let function = compile("fun loop(n) { return 1 + loop(n); } loop(1);").unwrap();
+    let error = vm.interpret(function).unwrap_err();
+    assert_eq!(ErrorKind::StackOverflow, error.kind);
+    assert_eq!(8, error.trace.len());
+
+    // The VM stays usable after an error
+    run(&mut vm, "var after = 1;");
+    assert_eq!(Some(Value::Number(1.0)), vm.get_global("after"));
+}
+
+#[test]
+fn closure_natives() {
+    let calls = Rc::new(Cell::new(0));
+    let mut vm = Vm::new();
+
+    let counter = calls.clone();
+    vm.define_native("tick", 0, move |_, _| {
+        counter.set(counter.get() + 1);
+        Ok(Value::Number(counter.get() as f64))
+    });
+    vm.define_native("other", 0, |_, _| Ok(Value::Nil));
+
+    run(
+        &mut vm,
+        r#"
+tick();
+tick();
+var count = tick();
+var alias = tick;
+var same = alias == tick;
+var different = tick == other;
+"#,
+    );
+    assert_eq!(3, calls.get());
+    assert_eq!(Some(Value::Number(3.0)), vm.get_global("count"));
+    assert_eq!(Some(Value::Boolean(true)), vm.get_global("same"));
+    assert_eq!(Some(Value::Boolean(false)), vm.get_global("different"));
+    assert_eq!(
+        "<native fun tick>",
+        vm.get_global("alias").unwrap().to_string()
+    );
+}
+
+#[test]
+fn call_from_host() {
+    let mut vm = Vm::new();
+    run(
+        &mut vm,
+        r#"
+var events = 0;
+fun on_event(payload) {
+    events = events + 1;
+    return "handled " + payload;
+}
+fun broken(payload) {
+    return payload + nil;
+}
+"#,
+    );
+
+    let handler = vm.get_global("on_event").unwrap();
+    let result = vm.call_function(&handler, &[Value::new_string("click")]);
+    assert_eq!(Ok(Value::new_string("handled click")), result);
+    vm.call_function(&handler, &[Value::new_string("key")])
+        .unwrap();
+    assert_eq!(Some(Value::Number(2.0)), vm.get_global("events"));
+
+    let error = vm.call_function(&handler, &[]).unwrap_err();
+    assert_eq!(ErrorKind::Arity, error.kind);
+
+    let broken = vm.get_global("broken").unwrap();
+    let error = vm
+        .call_function(&broken, &[Value::Number(1.0)])
+        .unwrap_err();
+    assert_eq!(ErrorKind::Type, error.kind);
+    assert_eq!(1, error.trace.len());
+
+    // Failed calls leave the VM ready for the next one
+    let result = vm.call_function(&handler, &[Value::new_string("again")]);
+    assert_eq!(Ok(Value::new_string("handled again")), result);
+
+    let to_hex = vm.get_global("to_hex").unwrap();
+    let result = vm.call_function(&to_hex, &[Value::Number(255.0)]);
+    assert_eq!(Ok(Value::new_string("0xff")), result);
+
+    let error = vm.call_function(&Value::Nil, &[]).unwrap_err();
+    assert_eq!(ErrorKind::NotCallable, error.kind);
+}
+
+#[test]
+fn functions_of_other_vms() {
+    let mut owner = Vm::new();
+    let script = owner
+        .compile("var x = 1; var y = 2; fun sum() { return x + y; }")
+        .unwrap();
+    owner.interpret(script).unwrap();
+    let sum = owner.get_global("sum").unwrap();
+
+    // The global slots of `owner` mean nothing to another VM
+    let mut other = Vm::new();
+    run(&mut other, "var y = 3;");
+    let error = other.call_function(&sum, &[]).unwrap_err();
+    assert_eq!(ErrorKind::NotCallable, error.kind);
+    assert_eq!(
+        "Can't call 'sum', it was compiled for another VM",
+        error.message
+    );
+
+    let script = owner.compile("print x + y;").unwrap();
+    let error = other.interpret(script).unwrap_err();
+    assert_eq!(ErrorKind::NotCallable, error.kind);
+
+    other.set_global("callback", sum.clone());
+    let error = other
+        .interpret(compile("callback();").unwrap())
+        .unwrap_err();
+    assert_eq!(ErrorKind::NotCallable, error.kind);
+    assert_eq!(1, error.trace.len());
+
+    // Scripts compiled without a VM can run on any of them
+    let script = compile("fun sum() { return x + y; } var x = 10;").unwrap();
+    other.interpret(script.clone()).unwrap();
+    owner.interpret(script).unwrap();
+    assert_eq!(Ok(Value::Number(12.0)), owner.call_function(&sum, &[]));
+    let other_sum = other.get_global("sum").unwrap();
+    assert_eq!(
+        Ok(Value::Number(13.0)),
+        other.call_function(&other_sum, &[])
+    );
+}
+
+fn apply_twice(ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+    let once = ctx.call_function(&args[0], &args[1..])?;
+    ctx.call_function(&args[0], &[once])
+}
+
+#[test]
+fn callbacks_from_natives() {
+    let mut vm = Vm::new();
+    vm.define_native("apply_twice", 2, apply_twice);
+
+    run(
+        &mut vm,
+        r#"
+fun double(n) {
+    return n * 2;
+}
+fun nested(n) {
+    return apply_twice(double, n) + 1;
+}
+var result = apply_twice(nested, 1);
+"#,
+    );
+    assert_eq!(Some(Value::Number(21.0)), vm.get_global("result"));
+
+    let function = compile(
+        "fun fail(n) {\n    return n + nil;\n}\nfun outer() {\n    return apply_twice(fail, 1);\n}\nouter();",
+    )
+    .unwrap();
+    let error = vm.interpret(function).unwrap_err();
+    assert_eq!(ErrorKind::Type, error.kind);
+    let functions: Vec<&str> = error.trace.iter().map(|frame| &*frame.function).collect();
+    assert_eq!(vec!["fail", "outer", "<main>"], functions);
+}
+
+fn add(a: f64, b: f64) -> f64 {
+    a + b
+}
+
+fn join(parts: Variadic<String>) -> String {
+    parts.concat()
+}
+
+fn checked_div(a: i64, b: i64) -> Result<Option<i64>, VmError> {
+    if b == 0 {
+        return Err(VmError::new(ErrorKind::Native, "Division by zero"));
+    }
+    Ok((a % b == 0).then(|| a / b))
+}
+
+#[test]
+fn typed_natives() {
+    let mut vm = Vm::new();
+    vm.define_typed_native("add", add);
+    vm.define_typed_native("join", join);
+    vm.define_typed_native("checked_div", checked_div);
+    vm.define_typed_native("greet", |name: Option<String>| {
+        format!("Hello, {}", name.as_deref().unwrap_or("stranger"))
+    });
+
+    run(
+        &mut vm,
+        r#"
+var sum = add(1, 5 / 2);
+var joined = join("a", "b", "c");
+var empty = join();
+var exact = checked_div(6, 3);
+var inexact = checked_div(7, 2);
+var named = greet("host");
+var anonymous = greet(nil);
+"#,
+    );
+    assert_eq!(Some(Value::Number(3.5)), vm.get_global("sum"));
+    assert_eq!(Some(Value::new_string("abc")), vm.get_global("joined"));
+    assert_eq!(Some(Value::new_string("")), vm.get_global("empty"));
+    assert_eq!(Some(Value::Number(2.0)), vm.get_global("exact"));
+    assert_eq!(Some(Value::Nil), vm.get_global("inexact"));
+    assert_eq!(
+        Some(Value::new_string("Hello, host")),
+        vm.get_global("named")
+    );
+    assert_eq!(
+        Some(Value::new_string("Hello, stranger")),
+        vm.get_global("anonymous")
+    );
+
+    let mut error = |source: &str| vm.interpret(compile(source).unwrap()).unwrap_err();
+
+    let arity = error("add(1);");
+    assert_eq!(ErrorKind::Arity, arity.kind);
+    assert_eq!("add expected 2 arguments, got 1", arity.message);
+
+    let mismatch = error("add(1, \"two\");");
+    assert_eq!(ErrorKind::Type, mismatch.kind);
+    assert_eq!(
+        "add expected a number as argument 2, got two",
+        mismatch.message
+    );
+
+    let variadic = error("join(\"a\", true);");
+    assert_eq!(
+        "join expected a string as argument 2, got true",
+        variadic.message
+    );
+
+    let optional = error("greet(1);");
+    assert_eq!(
+        "greet expected a string or nil as argument 1, got 1",
+        optional.message
+    );
+
+    let integer = error("checked_div(3 / 2, 1);");
+    assert_eq!(
+        "checked_div expected an integer as argument 1, got 1.5",
+        integer.message
+    );
+
+    let failed = error("checked_div(1, 0);");
+    assert_eq!(ErrorKind::Native, failed.kind);
+    assert_eq!(1, failed.trace.len());
+}
+
+fn round_trip<T: IntoLox + FromLox + Clone + PartialEq + std::fmt::Debug>(value: T) {
+    assert_eq!(Some(value.clone()), T::from_lox(&value.into_lox()));
+}
+
+#[test]
+fn conversions_round_trip() {
+    round_trip(1.5);
+    round_trip(true);
+    round_trip("text".to_owned());
+    round_trip(Some(3.0));
+    round_trip(None::<f64>);
+    round_trip(-42i64);
+    round_trip(1i64 << 60);
+    round_trip(i64::MAX);
+    round_trip(i64::MIN);
+    round_trip(vec![1.0, 2.0, 3.0]);
+    round_trip(Vec::<bool>::new());
+    round_trip((1.0, "two".to_owned(), Some(true)));
+    round_trip(vec![(1i64, vec!["a".to_owned()])]);
+
+    let big = (1i64 << 60).into_lox();
+    assert_eq!("1152921504606846976", big.to_string());
+    assert_eq!(None, i64::from_lox(&(i64::MAX as f64 * 2.0).into_lox()));
+    assert_eq!(None, i64::from_lox(&Value::Number(0.5)));
+
+    let list = vec![1.0, 2.0].into_lox();
+    assert_eq!("[1, 2]", list.to_string());
+    assert_eq!(None, <(f64, f64, f64)>::from_lox(&list));
+    assert_eq!(None, Vec::<String>::from_lox(&list));
+}
+
+#[test]
+fn list_values() {
+    let mut vm = Vm::new();
+    vm.define_typed_native("range", |n: i64| (0..n).collect::<Vec<i64>>());
+    vm.define_typed_native("total", |items: Vec<f64>| items.iter().sum::<f64>());
+    vm.define_typed_native("swap", |pair: (String, f64)| (pair.1, pair.0));
+    vm.set_global("pair", ("a".to_owned(), 2.0).into_lox());
+
+    run(
+        &mut vm,
+        r#"
+var numbers = range(4);
+numbers.push(10);
+numbers.set(0, 5);
+var sum = total(numbers);
+var count = numbers.len();
+var swapped = swap(pair);
+var first = swapped.get(0);
+numbers.push(numbers);
+"#,
+    );
+    assert_eq!(Some(Value::Number(21.0)), vm.get_global("sum"));
+    assert_eq!(Some(Value::Number(5.0)), vm.get_global("count"));
+    assert_eq!(Some(Value::Number(2.0)), vm.get_global("first"));
+    assert_eq!(
+        Some((2.0, "a".to_owned())),
+        FromLox::from_lox(&vm.get_global("swapped").unwrap())
+    );
+
+    let numbers = vm.get_global("numbers").unwrap();
+    assert_eq!(6, numbers.as_userdata::<List>().unwrap().len());
+    assert_eq!("[5, 1, 2, 3, 10, [...]]", numbers.to_string());
+
+    let mut error = |source: &str| vm.interpret(compile(source).unwrap()).unwrap_err();
+
+    let mismatch = error("total(numbers);");
+    assert_eq!(ErrorKind::Type, mismatch.kind);
+    assert_eq!(
+        "total expected a list whose items are each a number as argument 1, got [5, 1, 2, 3, 10, [...]]",
+        mismatch.message
+    );
+
+    let tuple = error("swap(range(2));");
+    assert_eq!(
+        "swap expected a list of (a string, a number) as argument 1, got [0, 1]",
+        tuple.message
+    );
+
+    let index = error("pair.get(2);");
+    assert_eq!("List index 2 is out of range for length 2", index.message);
+}
@@ -0,0 +1,271 @@
+use loxide::{compile, Arity, Method, NativeContext, UserData, Value, Vm, VmConfig, VmError};
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    rc::Rc,
+};
+
+/// Host container that can hold itself
+struct List {
+    items: RefCell<Vec<Value>>,
+    freed: Rc<Cell<usize>>,
+}
+
+impl List {
+    fn push(&self, _ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+        self.items.borrow_mut().push(args[0].clone());
+        Ok(Value::Nil)
+    }
+
+    fn len(&self, _ctx: &mut NativeContext, _args: &[Value]) -> Result<Value, VmError> {
+        Ok(Value::Number(self.items.borrow().len() as f64))
+    }
+}
+
+impl UserData for List {
+    const METHODS: &'static [Method<Self>] = &[
+        Method {
+            name: "push",
+            arity: Arity { min: 1, max: 1 },
+            function: List::push,
+        },
+        Method {
+            name: "len",
+            arity: Arity { min: 0, max: 0 },
+            function: List::len,
+        },
+    ];
+
+    fn type_name(&self) -> &str {
+        "List"
+    }
+
+    fn trace(&self, visit: &mut dyn FnMut(&Value)) {
+        self.items.borrow().iter().for_each(visit);
+    }
+
+    fn clear(&self) {
+        self.items.borrow_mut().clear();
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<List of {}>", self.items.borrow().len())
+    }
+}
+
+impl Drop for List {
+    fn drop(&mut self) {
+        self.freed.set(self.freed.get() + 1);
+    }
+}
+
+fn vm_with_lists(config: VmConfig) -> (Vm, Rc<Cell<usize>>) {
+    let freed = Rc::new(Cell::new(0));
+    let mut vm = Vm::with_config(config);
+
+    let counter = freed.clone();
+    vm.define_native("list", 0, move |_, _| {
+        Ok(Value::new_userdata(List {
+            items: RefCell::new(Vec::new()),
+            freed: counter.clone(),
+        }))
+    });
+    (vm, freed)
+}
+
+fn run(vm: &mut Vm, source: &str) {
+    let function = vm.compile(source).unwrap();
+    vm.interpret(function).unwrap();
+}
+
+#[test]
+fn collects_cycles() {
+    let (mut vm, freed) = vm_with_lists(VmConfig::default());
+
+    run(
+        &mut vm,
+        r#"
+var a = list();
+var b = list();
+a.push(b);
+b.push(a);
+a.push(a);
+a.push("text");
+var kept = list();
+kept.push(kept);
+"#,
+    );
+    run(&mut vm, "a = nil; b = nil;");
+    assert_eq!(0, freed.get());
+
+    vm.collect_garbage();
+    assert_eq!(2, freed.get());
+
+    run(&mut vm, "kept.push(1); var size = kept.len();");
+    assert_eq!(Some(Value::Number(2.0)), vm.get_global("size"));
+}
+
+#[test]
+fn frees_objects_in_collections() {
+    let (mut vm, freed) = vm_with_lists(VmConfig::default());
+
+    run(&mut vm, "var a = list(); a.push(\"text\"); a = nil;");
+    // The heap owns the list, dropping the last reference doesn't free it
+    assert_eq!(0, freed.get());
+
+    vm.collect_garbage();
+    assert_eq!(1, freed.get());
+}
+
+#[test]
+fn keeps_objects_held_by_the_host() {
+    let (mut vm, freed) = vm_with_lists(VmConfig::default());
+
+    run(
+        &mut vm,
+        "var a = list(); var b = list(); a.push(b); b.push(a);",
+    );
+    let held = vm.get_global("a").unwrap();
+    run(&mut vm, "a = nil; b = nil;");
+
+    vm.collect_garbage();
+    assert_eq!(0, freed.get());
+    assert_eq!("<List of 1>", held.to_string());
+
+    drop(held);
+    vm.collect_garbage();
+    assert_eq!(2, freed.get());
+}
+
+#[test]
+fn collects_on_allocation_threshold() {
+    let (mut vm, freed) = vm_with_lists(VmConfig {
+        gc_threshold: 4096,
+        ..Default::default()
+    });
+
+    run(
+        &mut vm,
+        r#"
+for (var i = 0; i < 1000; i = i + 1) {
+    var cycle = list();
+    cycle.push(cycle);
+}
+"#,
+    );
+    assert!(freed.get() > 900, "freed {} lists", freed.get());
+}
+
+#[test]
+fn stress_mode() {
+    let (mut vm, freed) = vm_with_lists(VmConfig {
+        gc_stress: true,
+        ..Default::default()
+    });
+
+    run(
+        &mut vm,
+        r#"
+fun describe(n) {
+    var text = "n = " + to_decimal(n);
+    return text + "!";
+}
+var items = list();
+var big = 9007199254740993;
+for (var i = 0; i < 50; i = i + 1) {
+    items.push(describe(i));
+    big = big * 3;
+    var garbage = list();
+    garbage.push(garbage);
+}
+var last = describe(49);
+var count = items.len();
+"#,
+    );
+    assert_eq!(Some(Value::new_string("n = 49!")), vm.get_global("last"));
+    assert_eq!(Some(Value::Number(50.0)), vm.get_global("count"));
+    assert_eq!(50, freed.get());
+    assert!(vm.get_global("big").unwrap().to_string().len() > 30);
+}
+
+#[test]
+fn frees_unused_interned_strings() {
+    let mut vm = Vm::new();
+    let usage = vm.memory_usage();
+
+    run(
+        &mut vm,
+        r#"
+{
+    var text = "";
+    for (var i = 0; i < 100; i = i + 1) {
+        text = text + "x";
+    }
+}
+"#,
+    );
+    assert_eq!(usage, vm.memory_usage());
+}
+
+#[test]
+fn tracks_scripts_compiled_without_a_vm() {
+    let mut vm = Vm::new();
+    let usage = vm.memory_usage();
+
+    let text = "x".repeat(1000);
+    let function = compile(&format!("var text = \"{text}\";")).unwrap();
+    vm.interpret(function).unwrap();
+    assert!(vm.memory_usage() >= usage + 1000);
+
+    vm.set_global("text", Value::Nil);
+    assert_eq!(usage, vm.memory_usage());
+}
+
+#[test]
+fn objects_outlive_their_vm() {
+    let (mut vm, freed) = vm_with_lists(VmConfig::default());
+
+    run(
+        &mut vm,
+        r#"
+var held = list();
+held.push("text");
+var dropped = list();
+dropped.push(dropped);
+"#,
+    );
+    let held = vm.get_global("held").unwrap();
+    drop(vm);
+    assert_eq!(1, freed.get());
+    assert_eq!("<List of 1>", held.to_string());
+
+    // Without a VM, objects are freed with their last handle
+    drop(held);
+    assert_eq!(2, freed.get());
+}
+
+#[test]
+fn objects_shared_between_vms() {
+    let (mut owner, freed) = vm_with_lists(VmConfig::default());
+    run(&mut owner, "var shared = list(); shared.push(\"text\");");
+
+    let mut other = Vm::with_config(VmConfig {
+        gc_stress: true,
+        ..Default::default()
+    });
+    other.set_global("shared", owner.get_global("shared").unwrap());
+    run(&mut owner, "shared = nil;");
+    owner.collect_garbage();
+    drop(owner);
+    assert_eq!(0, freed.get());
+
+    run(
+        &mut other,
+        "shared.push(\"more\"); var size = shared.len();",
+    );
+    assert_eq!(Some(Value::Number(2.0)), other.get_global("size"));
+    drop(other);
+    assert_eq!(1, freed.get());
+}
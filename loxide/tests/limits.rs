@@ -0,0 +1,196 @@
+use loxide::{compile, ErrorKind, List, NativeContext, Value, Vm, VmConfig, VmError};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+fn run(vm: &mut Vm, source: &str) -> Result<Option<Value>, VmError> {
+    vm.interpret(compile(source).unwrap())
+}
+
+#[test]
+fn out_of_fuel() {
+    let mut vm = Vm::new();
+    vm.set_fuel(Some(1000));
+
+    let error = run(&mut vm, "while (true) {}").unwrap_err();
+    assert_eq!(ErrorKind::OutOfFuel, error.kind);
+    assert_eq!(Some(0), vm.fuel());
+
+    let error = run(&mut vm, "fun f() { return f(); } f();").unwrap_err();
+    assert_eq!(ErrorKind::OutOfFuel, error.kind);
+
+    vm.set_fuel(None);
+    run(&mut vm, "var done = true;").unwrap();
+    assert_eq!(Some(Value::Boolean(true)), vm.get_global("done"));
+}
+
+#[test]
+fn resume_with_more_fuel() {
+    let mut vm = Vm::new();
+    vm.set_fuel(Some(10));
+
+    let source = r#"
+var total = 0;
+fun add(n) {
+    total = total + n;
+}
+for (var i = 1; i <= 100; i = i + 1) {
+    add(i);
+}
+"#;
+    let mut stops = 0;
+    let mut result = run(&mut vm, source);
+    while let Err(error) = result {
+        assert_eq!(ErrorKind::OutOfFuel, error.kind);
+        stops += 1;
+        vm.set_fuel(Some(10));
+        result = vm.resume();
+    }
+
+    assert!(stops >= 19, "stopped {stops} times");
+    assert_eq!(Some(Value::Number(5050.0)), vm.get_global("total"));
+    assert_eq!(Ok(None), vm.resume());
+}
+
+#[test]
+fn deadline() {
+    let mut vm = Vm::new();
+    vm.set_deadline(Some(Instant::now() + Duration::from_millis(50)));
+
+    let started = Instant::now();
+    let error = run(&mut vm, "var i = 0; while (true) { i = i + 1; }").unwrap_err();
+    assert_eq!(ErrorKind::Timeout, error.kind);
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // Extending the deadline lets the script continue where it stopped
+    let count = vm.get_global("i").unwrap().as_number().unwrap();
+    vm.set_deadline(Some(Instant::now() + Duration::from_millis(20)));
+    assert_eq!(ErrorKind::Timeout, vm.resume().unwrap_err().kind);
+    assert!(vm.get_global("i").unwrap().as_number().unwrap() > count);
+}
+
+fn callback(ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+    ctx.call_function(&args[0], &[])
+}
+
+#[test]
+fn out_of_fuel_in_callback() {
+    let mut vm = Vm::new();
+    vm.define_native("callback", 1, callback);
+    vm.set_fuel(Some(100));
+
+    let source = "fun spin() { while (true) {} } callback(spin);";
+    let error = run(&mut vm, source).unwrap_err();
+    assert_eq!(ErrorKind::OutOfFuel, error.kind);
+
+    // The native can't be continued, so there is nothing to resume
+    vm.set_fuel(Some(100));
+    assert_eq!(Ok(None), vm.resume());
+}
+
+fn limited(max_memory: usize) -> Vm {
+    Vm::with_config(VmConfig {
+        max_memory: Some(max_memory),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn out_of_memory() {
+    let mut vm = limited(1 << 20);
+
+    let error = run(&mut vm, "var s = \"ab\"; while (true) { s = s + s; }").unwrap_err();
+    assert_eq!(ErrorKind::OutOfMemory, error.kind);
+    assert!(vm.peak_memory_usage() <= 1 << 20);
+
+    let mut vm = limited(4096);
+    let error = run(&mut vm, "var n = 2; while (true) { n = n * n; }").unwrap_err();
+    assert_eq!(ErrorKind::OutOfMemory, error.kind);
+}
+
+#[test]
+fn growing_host_objects() {
+    let mut vm = limited(64 * 1024);
+    vm.set_global("items", Value::new_userdata(List::default()));
+
+    let error = run(&mut vm, "while (true) { items.push(1); }").unwrap_err();
+    assert_eq!(ErrorKind::OutOfMemory, error.kind);
+    assert!(vm.peak_memory_usage() <= 64 * 1024);
+
+    // Lists that the host grows are measured again by the next collection
+    let items = vm.get_global("items").unwrap();
+    let list = items.as_userdata::<List>().unwrap();
+    let usage = vm.memory_usage();
+    assert!(usage >= list.len() * 16, "{usage} bytes in use");
+    for _ in 0..list.len() {
+        list.push(Value::Nil);
+    }
+    assert!(vm.memory_usage() >= usage * 2 - 1024);
+}
+
+#[test]
+fn freed_objects_are_not_counted() {
+    let mut vm = limited(64 * 1024);
+
+    run(
+        &mut vm,
+        r#"
+var last;
+for (var i = 0; i < 20000; i = i + 1) {
+    last = "item " + to_hex(i);
+}
+"#,
+    )
+    .unwrap();
+    assert!(vm.peak_memory_usage() <= 64 * 1024);
+}
+
+#[test]
+fn memory_usage() {
+    let mut vm = Vm::new();
+    assert_eq!(0, vm.memory_usage());
+
+    run(
+        &mut vm,
+        r#"
+var text = "a";
+for (var i = 0; i < 10; i = i + 1) {
+    text = text + text;
+}
+"#,
+    )
+    .unwrap();
+    let usage = vm.memory_usage();
+    assert!(usage >= 1024, "{usage} bytes in use");
+    assert!(vm.peak_memory_usage() >= usage + 512);
+
+    run(&mut vm, "text = nil;").unwrap();
+    assert_eq!(0, vm.memory_usage());
+}
+
+#[test]
+fn interrupt_from_another_thread() {
+    let mut vm = Vm::new();
+    let handle = vm.interrupt_handle();
+
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.interrupt();
+    });
+    let error = run(&mut vm, "fun spin() { while (true) {} } spin();").unwrap_err();
+    interrupter.join().unwrap();
+
+    assert_eq!(ErrorKind::Interrupted, error.kind);
+    assert_eq!(2, error.trace.len());
+    assert_eq!(Ok(None), vm.resume());
+
+    // An interrupt only stops the script that is running at the time
+    vm.interrupt_handle().interrupt();
+    run(
+        &mut vm,
+        "var after = 1; while (after < 10) after = after + 1;",
+    )
+    .unwrap();
+    assert_eq!(Some(Value::Number(10.0)), vm.get_global("after"));
+}
@@ -0,0 +1,54 @@
+use loxide::{compile, compile_with_warnings, CaptureBuffer, NativeContext, Value, Vm, VmError};
+
+fn shout(ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+    let text = ctx.alloc_string(format!("{}!", args[0]));
+    ctx.print(&text)?;
+    Ok(Value::Nil)
+}
+
+#[test]
+fn capture_print() {
+    let output = CaptureBuffer::new();
+    let mut vm = Vm::new();
+    vm.set_output(output.clone());
+    vm.define_native("shout", 1, shout);
+
+    let source = r#"
+for (var i = 1; i <= 3; i = i + 1) {
+    print i * 2;
+}
+print "done";
+shout("loud");
+"#;
+    vm.interpret(compile(source).unwrap()).unwrap();
+    assert_eq!("2\n4\n6\ndone\nloud!\n", output.contents());
+
+    output.clear();
+    vm.interpret(compile("print nil;").unwrap()).unwrap();
+    assert_eq!("nil\n", output.contents());
+}
+
+#[test]
+fn capture_diagnostics() {
+    let output = CaptureBuffer::new();
+    let diagnostics = CaptureBuffer::new();
+    let mut vm = Vm::new();
+    vm.set_output(output.clone());
+    vm.set_diagnostics(diagnostics.clone());
+
+    let source = "print 1;\nprint -nil;\n";
+    let error = vm.interpret(compile(source).unwrap()).unwrap_err();
+    vm.report_error(&error, source).unwrap();
+
+    assert_eq!("1\n", output.contents());
+    assert_eq!(
+        format!("{}\n", error.render(source)),
+        diagnostics.contents()
+    );
+
+    diagnostics.clear();
+    let source = "{ var unused; }";
+    let (_, warnings) = compile_with_warnings(source).unwrap();
+    vm.report_diagnostic(&warnings[0], source).unwrap();
+    assert!(diagnostics.contents().starts_with("warning: "));
+}
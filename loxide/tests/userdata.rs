@@ -0,0 +1,157 @@
+use loxide::{compile, Arity, ErrorKind, Method, NativeContext, UserData, Value, Vm, VmError};
+use std::{cell::Cell, fmt};
+
+struct Counter {
+    count: Cell<f64>,
+}
+
+impl Counter {
+    fn add(&self, _ctx: &mut NativeContext, args: &[Value]) -> Result<Value, VmError> {
+        let amount = args.first().and_then(Value::as_number).unwrap_or(1.0);
+        self.count.set(self.count.get() + amount);
+        Ok(Value::Nil)
+    }
+
+    fn get(&self, _ctx: &mut NativeContext, _args: &[Value]) -> Result<Value, VmError> {
+        Ok(Value::Number(self.count.get()))
+    }
+}
+
+impl UserData for Counter {
+    const METHODS: &'static [Method<Self>] = &[
+        Method {
+            name: "add",
+            arity: Arity { min: 0, max: 1 },
+            function: Counter::add,
+        },
+        Method {
+            name: "get",
+            arity: Arity { min: 0, max: 0 },
+            function: Counter::get,
+        },
+    ];
+
+    fn type_name(&self) -> &str {
+        "Counter"
+    }
+}
+
+impl fmt::Display for Counter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<Counter {}>", self.count.get())
+    }
+}
+
+struct Point(i32, i32);
+
+impl UserData for Point {
+    const METHODS: &'static [Method<Self>] = &[];
+
+    fn type_name(&self) -> &str {
+        "Point"
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        self.0 == other.0 && self.1 == other.1
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<Point {}, {}>", self.0, self.1)
+    }
+}
+
+fn run(vm: &mut Vm, source: &str) -> Result<Option<Value>, VmError> {
+    vm.interpret(compile(source).unwrap())
+}
+
+#[test]
+fn call_methods() {
+    let mut vm = Vm::new();
+    vm.set_global(
+        "counter",
+        Value::new_userdata(Counter {
+            count: Cell::new(0.0),
+        }),
+    );
+
+    run(
+        &mut vm,
+        r#"
+fun bump(value) {
+    value.add();
+    value.add(10);
+}
+bump(counter);
+var alias = counter;
+alias.add(2);
+var total = counter.get();
+var text = counter.get() + 1;
+var same = alias == counter;
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(Some(Value::Number(13.0)), vm.get_global("total"));
+    assert_eq!(Some(Value::Number(14.0)), vm.get_global("text"));
+    assert_eq!(Some(Value::Boolean(true)), vm.get_global("same"));
+
+    let counter = vm.get_global("counter").unwrap();
+    assert_eq!(13.0, counter.as_userdata::<Counter>().unwrap().count.get());
+    assert!(counter.as_userdata::<Point>().is_none());
+    assert_eq!("<Counter 13>", counter.to_string());
+}
+
+#[test]
+fn custom_equality() {
+    let mut vm = Vm::new();
+    vm.set_global("a", Value::new_userdata(Point(1, 2)));
+    vm.set_global("b", Value::new_userdata(Point(1, 2)));
+    vm.set_global("c", Value::new_userdata(Point(2, 1)));
+    vm.set_global(
+        "counter",
+        Value::new_userdata(Counter {
+            count: Cell::new(0.0),
+        }),
+    );
+
+    run(
+        &mut vm,
+        "var equal = a == b; var different = a == c; var mixed = a == counter;",
+    )
+    .unwrap();
+    assert_eq!(Some(Value::Boolean(true)), vm.get_global("equal"));
+    assert_eq!(Some(Value::Boolean(false)), vm.get_global("different"));
+    assert_eq!(Some(Value::Boolean(false)), vm.get_global("mixed"));
+}
+
+#[test]
+fn method_errors() {
+    let mut vm = Vm::new();
+    vm.set_global(
+        "counter",
+        Value::new_userdata(Counter {
+            count: Cell::new(0.0),
+        }),
+    );
+
+    let error = run(&mut vm, "counter.reset();").unwrap_err();
+    assert_eq!(ErrorKind::UndefinedProperty, error.kind);
+    assert_eq!("Counter has no method 'reset'", error.message);
+    assert_eq!(1, error.trace.len());
+    assert_eq!(9, error.trace[0].column);
+
+    let error = run(&mut vm, "counter.get(1);").unwrap_err();
+    assert_eq!(ErrorKind::Arity, error.kind);
+    assert_eq!("Counter.get expected 0 arguments, got 1", error.message);
+
+    let error = run(&mut vm, "\"text\".get();").unwrap_err();
+    assert_eq!(ErrorKind::Type, error.kind);
+
+    let diagnostics = compile("print counter.count;").unwrap_err();
+    assert_eq!(
+        "Expected a '(' after the method name",
+        diagnostics[0].message
+    );
+}